@@ -3,8 +3,25 @@ use std::default::Default;
 
 
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod color_space;
+pub mod compositor;
+pub mod compressor;
+pub mod custom;
+pub mod decoder;
 pub mod encoder;
+pub mod frame_writer;
+mod interlace;
+#[allow(non_local_definitions)]
 pub mod errors;
+pub mod merger;
+pub mod metadata;
+pub mod optimizer;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod quantizer;
+pub mod text;
 
 
 
@@ -23,7 +40,7 @@ pub struct Meta {
 pub enum Color {
     Grayscale(u8),
     GrayscaleA(u8),
-    // Palette,
+    Palette(u8),
     RGB(u8),
     RGBA(u8),
 }
@@ -64,10 +81,24 @@ impl Color {
         use self::Color::*;
 
         match self {
-            Grayscale(b) | GrayscaleA(b) | RGB(b) | RGBA(b) => b,
+            Grayscale(b) | GrayscaleA(b) | Palette(b) | RGB(b) | RGBA(b) => b,
         }
     }
 
+    /// Bits per pixel
+    pub fn pixel_bits(self) -> usize {
+        use self::Color::*;
+
+        let channels = match self {
+            Grayscale(_) | Palette(_) => 1,
+            GrayscaleA(_) => 2,
+            RGB(_) => 3,
+            RGBA(_) => 4,
+        };
+        channels * self.bit_depth() as usize
+    }
+
+    /// Bytes per pixel, rounded up to 1 for the colors under 8 bits
     pub fn pixel_bytes(self) -> usize {
         use self::Color::*;
 
@@ -76,12 +107,18 @@ impl Color {
             Grayscale(_) => 1,
             GrayscaleA(16) => 4,
             GrayscaleA(_) => 2,
+            Palette(_) => 1,
             RGB(16) => 6,
             RGB(_) => 3,
             RGBA(16) => 8,
             RGBA(_) => 4,
         }
     }
+
+    /// Bytes of packed row
+    pub fn row_bytes(self, width: u32) -> usize {
+        (width as usize * self.pixel_bits() + 7) / 8
+    }
 }
 
 
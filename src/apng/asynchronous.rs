@@ -0,0 +1,98 @@
+
+use std::io;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::{Frame, Meta};
+use super::encoder::{Encoder, Filter};
+use super::errors::ApngResult;
+
+
+/// APNG Encoder that writes chunks to `tokio::io::AsyncWrite`
+///
+/// The wrapped `Encoder` writes chunks into memory with the same validation and sequencing,
+/// and they are sent to the async writer after each frame.
+/// Chunks buffered by `Encoder::create_buffered` are sent in `finish`.
+///
+/// `flush`, `write_default_image` and `write_frame` are cancel safe.
+/// If the future is dropped, the image is already written to the wrapped encoder, and the unsent chunks are sent by the next `flush`.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{AsyncEncoder, Color, Encoder, Meta};
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = AsyncEncoder::new(Encoder::create(vec![], meta).unwrap(), &mut buffer);
+/// encoder.write_frame(&[0x00], None, None, None).await.unwrap();
+/// encoder.write_frame(&[0xFF], None, None, None).await.unwrap();
+/// encoder.finish().await.unwrap();
+/// # });
+/// ```
+#[derive(Debug)]
+pub struct AsyncEncoder<W: AsyncWrite + Unpin> {
+    encoder: Encoder<Vec<u8>>,
+    writer: W,
+}
+
+
+impl<W: AsyncWrite + Unpin> AsyncEncoder<W> {
+    /// `encoder` should be created with an empty `Vec`. The chunks written so far (e.g. IHDR) are sent with the first frame.
+    pub fn new(encoder: Encoder<Vec<u8>>, writer: W) -> Self {
+        AsyncEncoder { encoder, writer }
+    }
+
+    pub async fn finish(self) -> ApngResult<()> {
+        self.into_inner().await.map(|_| ())
+    }
+
+    /// Finish the stream like `finish`, and return the writer
+    pub async fn into_inner(self) -> ApngResult<W> {
+        let AsyncEncoder { encoder, mut writer } = self;
+        let rest = encoder.into_inner()?;
+        writer.write_all(&rest).await?;
+        writer.flush().await?;
+        Ok(writer)
+    }
+
+    /// Send the chunks written so far to the writer
+    pub async fn flush(&mut self) -> ApngResult<()> {
+        // The sent bytes are removed after each write, so that they are not sent again when the future is dropped.
+        // `drain` keeps the capacity for the next frame.
+        while !self.encoder.get_ref().is_empty() {
+            let sent = self.writer.write(self.encoder.get_ref()).await?;
+            if sent == 0 {
+                return Err(io::Error::from(io::ErrorKind::WriteZero).into());
+            }
+            self.encoder.get_mut().drain(.. sent);
+        }
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// The wrapped encoder to write the other chunks (e.g. `Encoder::write_custom_chunk` between frames).
+    /// They are sent with the next frame.
+    pub fn encoder_mut(&mut self) -> &mut Encoder<Vec<u8>> {
+        &mut self.encoder
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn meta(&self) -> &Meta {
+        self.encoder.meta()
+    }
+
+    pub async fn write_default_image(&mut self, image_data: &[u8], filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        self.encoder.write_default_image(image_data, filter, row_stride)?;
+        self.flush().await
+    }
+
+    pub async fn write_frame(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        self.encoder.write_frame(image_data, frame, filter, row_stride)?;
+        self.flush().await
+    }
+}
@@ -0,0 +1,204 @@
+
+use std::io;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use super::Color;
+use super::encoder::{ChunkPosition, Encoder};
+use super::errors::{ApngResult, ApngError};
+use super::text::to_keyword;
+
+
+/// Rendering intent of sRGB chunk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RenderingIntent {
+    Perceptual = 0,
+    RelativeColorimetric = 1,
+    Saturation = 2,
+    AbsoluteColorimetric = 3,
+}
+
+/// CIE 1931 (x, y) chromaticities of cHRM chunk. Each value is multiplied by 100000.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Chromaticities {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+/// Coding-independent code points (ITU-T H.273) of cICP chunk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CodingIndependentCodePoints {
+    pub color_primaries: u8,
+    pub transfer_function: u8,
+    /// Must be 0 (RGB), because PNG has no YCbCr
+    pub matrix_coefficients: u8,
+    pub video_full_range: bool,
+}
+
+/// Mastering display color volume of mDCv chunk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MasteringDisplay {
+    /// (x, y) chromaticities in 0.00002 units
+    pub red: (u16, u16),
+    pub green: (u16, u16),
+    pub blue: (u16, u16),
+    pub white: (u16, u16),
+    /// Luminance in 0.0001 cd/m^2 units
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+/// Content light level information of cLLi chunk. Each value is in 0.0001 cd/m^2 units.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ContentLightLevel {
+    /// MaxCLL
+    pub max_content: u32,
+    /// MaxFALL
+    pub max_frame_average: u32,
+}
+
+
+impl Default for RenderingIntent {
+    fn default() -> Self {
+        RenderingIntent::Perceptual
+    }
+}
+
+impl CodingIndependentCodePoints {
+    /// BT.2100 with perceptual quantization
+    pub const BT2100_PQ: Self = CodingIndependentCodePoints {
+        color_primaries: 9,
+        transfer_function: 16,
+        matrix_coefficients: 0,
+        video_full_range: true,
+    };
+
+    /// BT.2100 with hybrid log-gamma
+    pub const BT2100_HLG: Self = CodingIndependentCodePoints {
+        color_primaries: 9,
+        transfer_function: 18,
+        matrix_coefficients: 0,
+        video_full_range: true,
+    };
+}
+
+
+/// Color space chunks must be written before `write_palette`, `write_default_image` and `write_frame`.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Encoder, Meta, RenderingIntent};
+///
+/// let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+/// encoder.write_srgb(RenderingIntent::Perceptual).unwrap();
+/// encoder.write_gamma(45455).unwrap();
+/// encoder.write_frame(&[0xFF, 0x00, 0x00], None, None, None).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+impl<F: io::Write> Encoder<F> {
+    /// Write sRGB chunk. Conflicts with iCCP chunk.
+    pub fn write_srgb(&mut self, intent: RenderingIntent) -> ApngResult<()> {
+        if self.has_written_chunk(*b"iCCP") {
+            return Err(ApngError::ConflictingChunk("sRGB".to_owned()));
+        }
+        self.write_unique_chunk(*b"sRGB", &[intent as u8], ChunkPosition::BeforePalette)
+    }
+
+    /// Write gAMA chunk. `gamma` is the image gamma multiplied by 100000 (e.g. 45455 for 1/2.2).
+    pub fn write_gamma(&mut self, gamma: u32) -> ApngResult<()> {
+        if gamma == 0 {
+            return Err(ApngError::InvalidArgument);
+        }
+        self.write_unique_chunk(*b"gAMA", &gamma.to_be_bytes(), ChunkPosition::BeforePalette)
+    }
+
+    /// Write cHRM chunk
+    pub fn write_chromaticities(&mut self, chromaticities: &Chromaticities) -> ApngResult<()> {
+        let mut buffer = vec![];
+        for (x, y) in &[chromaticities.white, chromaticities.red, chromaticities.green, chromaticities.blue] {
+            buffer.write_u32::<BigEndian>(*x)?;
+            buffer.write_u32::<BigEndian>(*y)?;
+        }
+        self.write_unique_chunk(*b"cHRM", &buffer, ChunkPosition::BeforePalette)
+    }
+
+    /// Write iCCP chunk with the compressed ICC profile. Conflicts with sRGB chunk.
+    ///
+    /// `name` must be 1-79 Latin-1 characters (e.g. "Display P3").
+    pub fn write_icc_profile(&mut self, name: &str, profile: &[u8]) -> ApngResult<()> {
+        if self.has_written_chunk(*b"sRGB") {
+            return Err(ApngError::ConflictingChunk("iCCP".to_owned()));
+        }
+        if profile.is_empty() {
+            return Err(ApngError::InvalidArgument);
+        }
+        let mut buffer = to_keyword(name)?;
+        // Null separator, Compression method
+        buffer.extend_from_slice(&[0, 0]);
+        self.compress_metadata(profile, &mut buffer)?;
+        self.write_unique_chunk(*b"iCCP", &buffer, ChunkPosition::BeforePalette)
+    }
+
+    /// Write cICP chunk.
+    /// Narrow range (`video_full_range = false`) is not defined for the bit depths under 8,
+    /// and HDR transfer functions (PQ and HLG) are rejected for the bit depths under 8 and indexed color.
+    pub fn write_code_points(&mut self, code_points: &CodingIndependentCodePoints) -> ApngResult<()> {
+        if code_points.matrix_coefficients != 0 {
+            return Err(ApngError::InvalidArgument);
+        }
+        if let 16 | 18 = code_points.transfer_function {
+            self.check_hdr_color()?;
+        }
+        if !code_points.video_full_range && self.meta().color.bit_depth() < 8 {
+            return Err(ApngError::InvalidColor);
+        }
+        let buffer = [
+            code_points.color_primaries,
+            code_points.transfer_function,
+            code_points.matrix_coefficients,
+            code_points.video_full_range as u8,
+        ];
+        self.write_unique_chunk(*b"cICP", &buffer, ChunkPosition::BeforePalette)
+    }
+
+    /// Write mDCv chunk. It describes HDR content, so the bit depths under 8 and indexed color are rejected.
+    pub fn write_mastering_display(&mut self, display: &MasteringDisplay) -> ApngResult<()> {
+        self.check_hdr_color()?;
+        if display.max_luminance <= display.min_luminance {
+            return Err(ApngError::InvalidArgument);
+        }
+        let mut buffer = vec![];
+        for (x, y) in &[display.red, display.green, display.blue, display.white] {
+            buffer.write_u16::<BigEndian>(*x)?;
+            buffer.write_u16::<BigEndian>(*y)?;
+        }
+        buffer.write_u32::<BigEndian>(display.max_luminance)?;
+        buffer.write_u32::<BigEndian>(display.min_luminance)?;
+        self.write_unique_chunk(*b"mDCv", &buffer, ChunkPosition::BeforeImageData)
+    }
+
+    /// Write cLLi chunk. It describes HDR content, so the bit depths under 8 and indexed color are rejected.
+    pub fn write_content_light_level(&mut self, level: &ContentLightLevel) -> ApngResult<()> {
+        self.check_hdr_color()?;
+        if level.max_content < level.max_frame_average {
+            return Err(ApngError::InvalidArgument);
+        }
+        let mut buffer = vec![];
+        buffer.write_u32::<BigEndian>(level.max_content)?;
+        buffer.write_u32::<BigEndian>(level.max_frame_average)?;
+        self.write_unique_chunk(*b"cLLi", &buffer, ChunkPosition::BeforeImageData)
+    }
+
+    fn check_hdr_color(&self) -> ApngResult<()> {
+        match self.meta().color {
+            Color::Palette(_) => Err(ApngError::InvalidColor),
+            color if color.bit_depth() < 8 => Err(ApngError::InvalidColor),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,319 @@
+
+use super::{BlendOperator, Color, DisposeOperator, Frame, Meta};
+use super::decoder::Decoded;
+use super::encoder::validate_color;
+use super::errors::{ApngResult, ApngError};
+use super::metadata::{Background, TransparentColor};
+
+
+/// Larger canvases are rejected with `ApngError::TooLargeImage`, instead of aborting on the allocation.
+/// The canvas of this size takes 1 GiB (RGBA of u16).
+pub const MAX_CANVAS_PIXELS: usize = 1 << 27;
+
+
+/// Renders frames into full canvas RGBA images, as viewers show them
+///
+/// Output images are RGBA with 16 bit samples (big endian) for 16 bit depth colors, or 8 bit samples for the others.
+/// With `set_background`, output images are flattened onto the background color, as viewers show them.
+/// The canvas itself is not changed, so `DisposeOperator::Background` still clears regions to transparent black.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{BlendOperator, Color, Compositor, Frame, Meta};
+///
+/// let meta = Meta { width: 2, height: 1, color: Color::GrayscaleA(8), frames: 2, plays: None };
+/// let mut compositor = Compositor::new(&meta).unwrap();
+/// let canvas = compositor.composite(&Frame::default(), &[0xFF, 0xFF, 0x00, 0xFF]).unwrap();
+/// assert_eq!(canvas, &[0xFF, 0xFF, 0xFF, 0xFF,   0x00, 0x00, 0x00, 0xFF]);
+///
+/// let frame = Frame { blend_operator: Some(BlendOperator::Over), ..Default::default() };
+/// let canvas = compositor.composite(&frame, &[0x00, 0x00, 0xFF, 0xFF]).unwrap();
+/// assert_eq!(canvas, &[0xFF, 0xFF, 0xFF, 0xFF,   0xFF, 0xFF, 0xFF, 0xFF]);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Compositor {
+    /// RGB samples
+    background: Option<[u16;3]>,
+    /// RGBA samples
+    canvas: Vec<u16>,
+    composited: usize,
+    disposal: Option<Disposal>,
+    meta: Meta,
+    palette: Option<Vec<[u8;4]>>,
+    /// Raw samples
+    transparent_color: Option<TransparentColor>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Disposal {
+    operator: DisposeOperator,
+    rect: Rect,
+    saved: Vec<u16>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Rect {
+    height: usize,
+    width: usize,
+    x: usize,
+    y: usize,
+}
+
+
+impl Compositor {
+    pub fn new(meta: &Meta) -> ApngResult<Self> {
+        validate_color(meta.color)?;
+        let pixels = (meta.width as usize).checked_mul(meta.height as usize)
+            .filter(|it| *it <= MAX_CANVAS_PIXELS)
+            .ok_or(ApngError::TooLargeImage)?;
+        Ok(Compositor {
+            background: None,
+            canvas: vec![0; pixels * 4],
+            composited: 0,
+            disposal: None,
+            meta: meta.clone(),
+            palette: None,
+            transparent_color: None,
+        })
+    }
+
+    /// Create a compositor for the decoded APNG, and render all frames
+    pub fn composite_decoded(decoded: &Decoded) -> ApngResult<Vec<Vec<u8>>> {
+        let mut compositor = Compositor::new(&decoded.meta)?;
+        if let Some(palette) = decoded.palette.as_ref() {
+            compositor.set_palette(palette, decoded.transparency.as_deref())?;
+        }
+        if let Some(transparency) = decoded.transparency.as_ref() {
+            if let Some(color) = TransparentColor::from_bytes(transparency, decoded.meta.color)? {
+                compositor.set_transparent_color(color)?;
+            }
+        }
+        if let Some(background) = decoded.background {
+            compositor.set_background(background)?;
+        }
+        decoded.frames.iter().map(|it| compositor.composite(&it.frame, &it.image_data)).collect()
+    }
+
+    /// Flatten output images onto `background` (bKGD chunk). `set_palette` is required before this for `Color::Palette`.
+    pub fn set_background(&mut self, background: Background) -> ApngResult<()> {
+        background.validate(self.meta.color, self.palette.as_ref().map(Vec::len))?;
+        let scale = |v: u16| match self.meta.color.bit_depth() {
+            8 | 16 => v,
+            b => v * 0xFF / ((1 << b) - 1),
+        };
+        self.background = Some(match background {
+            Background::Gray(v) => [scale(v); 3],
+            Background::RGB(r, g, b) => [r, g, b],
+            Background::PaletteIndex(index) => {
+                let entry = self.palette.as_ref().ok_or(ApngError::NoPalette)?[index as usize];
+                [u16::from(entry[0]), u16::from(entry[1]), u16::from(entry[2])]
+            },
+        });
+        Ok(())
+    }
+
+    /// Pixels of `color` (tRNS chunk for `Color::Grayscale` and `Color::RGB`) become fully transparent
+    pub fn set_transparent_color(&mut self, color: TransparentColor) -> ApngResult<()> {
+        color.validate(self.meta.color)?;
+        self.transparent_color = Some(color);
+        Ok(())
+    }
+
+    /// `palette` is a sequence of RGB triples, and `transparency` is a sequence of alpha values for the leading entries.
+    pub fn set_palette(&mut self, palette: &[u8], transparency: Option<&[u8]>) -> ApngResult<()> {
+        match self.meta.color {
+            Color::Palette(_) => (),
+            _ => return Err(ApngError::InvalidColor),
+        }
+        let transparency = transparency.unwrap_or(&[]);
+        let entries = palette.chunks_exact(3).enumerate().map(|(index, rgb)| {
+            [rgb[0], rgb[1], rgb[2], transparency.get(index).cloned().unwrap_or(0xFF)]
+        }).collect();
+        self.palette = Some(entries);
+        Ok(())
+    }
+
+    /// Render the frame on the canvas, and return the whole canvas.
+    /// `image_data` has packed rows (`ceil(width * pixel_bits / 8)` bytes per row).
+    pub fn composite(&mut self, frame: &Frame, image_data: &[u8]) -> ApngResult<Vec<u8>> {
+        let rect = self.compute_rect(frame)?;
+        let pixels = self.read_pixels(image_data, rect)?;
+
+        if let Some(disposal) = self.disposal.take() {
+            self.dispose(disposal);
+        }
+
+        let mut operator = frame.dispose_operator.unwrap_or_default();
+        if self.composited == 0 && operator == DisposeOperator::Previous {
+            operator = DisposeOperator::Background;
+        }
+        let saved = if operator == DisposeOperator::Previous { self.copy_rect(rect) } else { vec![] };
+
+        let max = u64::from(self.max_sample());
+        let over = frame.blend_operator.unwrap_or_default() == BlendOperator::Over;
+        for (row, line) in pixels.chunks(rect.width * 4).enumerate() {
+            let offset = self.canvas_offset(rect.x, rect.y + row);
+            let target = &mut self.canvas[offset .. offset + rect.width * 4];
+            if over {
+                for (dst, src) in target.chunks_mut(4).zip(line.chunks(4)) {
+                    blend_over(dst, src, max);
+                }
+            } else {
+                target.copy_from_slice(line);
+            }
+        }
+
+        self.disposal = Some(Disposal { operator, rect, saved });
+        self.composited += 1;
+        Ok(self.canvas_bytes())
+    }
+
+    fn canvas_bytes(&self) -> Vec<u8> {
+        let mut canvas = self.canvas.clone();
+        if let Some(background) = self.background {
+            let max = u64::from(self.max_sample());
+            for pixel in canvas.chunks_mut(4) {
+                let alpha = u64::from(pixel[3]);
+                for c in 0 .. 3 {
+                    let v = u64::from(pixel[c]) * alpha + u64::from(background[c]) * (max - alpha);
+                    pixel[c] = ((v + max / 2) / max) as u16;
+                }
+                pixel[3] = max as u16;
+            }
+        }
+
+        if self.meta.color.bit_depth() == 16 {
+            canvas.iter().flat_map(|it| it.to_be_bytes().to_vec()).collect()
+        } else {
+            canvas.iter().map(|it| *it as u8).collect()
+        }
+    }
+
+    fn canvas_offset(&self, x: usize, y: usize) -> usize {
+        (y * self.meta.width as usize + x) * 4
+    }
+
+    fn compute_rect(&self, frame: &Frame) -> ApngResult<Rect> {
+        let width = frame.width.unwrap_or(self.meta.width);
+        let height = frame.height.unwrap_or(self.meta.height);
+        let x = frame.x.unwrap_or(0);
+        let y = frame.y.unwrap_or(0);
+        if width == 0 || height == 0 {
+            return Err(ApngError::TooSmallImage);
+        }
+        let right = x.checked_add(width).ok_or(ApngError::TooLargeImage)?;
+        let bottom = y.checked_add(height).ok_or(ApngError::TooLargeImage)?;
+        if self.meta.width < right || self.meta.height < bottom {
+            return Err(ApngError::TooLargeImage);
+        }
+        Ok(Rect { height: height as usize, width: width as usize, x: x as usize, y: y as usize })
+    }
+
+    fn copy_rect(&self, rect: Rect) -> Vec<u16> {
+        let mut result = Vec::with_capacity(rect.width * rect.height * 4);
+        for row in 0 .. rect.height {
+            let offset = self.canvas_offset(rect.x, rect.y + row);
+            result.extend_from_slice(&self.canvas[offset .. offset + rect.width * 4]);
+        }
+        result
+    }
+
+    fn dispose(&mut self, disposal: Disposal) {
+        let rect = disposal.rect;
+        for row in 0 .. rect.height {
+            let offset = self.canvas_offset(rect.x, rect.y + row);
+            let target = &mut self.canvas[offset .. offset + rect.width * 4];
+            match disposal.operator {
+                DisposeOperator::None => return,
+                DisposeOperator::Background => target.iter_mut().for_each(|it| *it = 0),
+                DisposeOperator::Previous => {
+                    let width = rect.width * 4;
+                    target.copy_from_slice(&disposal.saved[row * width .. (row + 1) * width]);
+                },
+            }
+        }
+    }
+
+    fn max_sample(&self) -> u16 {
+        if self.meta.color.bit_depth() == 16 { 0xFFFF } else { 0xFF }
+    }
+
+    /// Convert to RGBA samples
+    fn read_pixels(&self, image_data: &[u8], rect: Rect) -> ApngResult<Vec<u16>> {
+        use self::Color::*;
+
+        let color = self.meta.color;
+        let bit_depth = color.bit_depth() as usize;
+        let row_bytes = color.row_bytes(rect.width as u32);
+        if image_data.len() < row_bytes * rect.height {
+            return Err(ApngError::TooSmallImage);
+        }
+
+        let max = self.max_sample();
+        let mut result = Vec::with_capacity(rect.width * rect.height * 4);
+        for line in image_data.chunks(row_bytes).take(rect.height) {
+            let sample = |index: usize| -> u16 {
+                match bit_depth {
+                    16 => u16::from_be_bytes([line[index * 2], line[index * 2 + 1]]),
+                    8 => u16::from(line[index]),
+                    _ => {
+                        let bit = index * bit_depth;
+                        let mask = (1 << bit_depth) - 1;
+                        u16::from(line[bit / 8] >> (8 - bit_depth - bit % 8)) & mask
+                    },
+                }
+            };
+            for x in 0 .. rect.width {
+                match color {
+                    Grayscale(b) => {
+                        let raw = sample(x);
+                        let v = if b < 8 { raw * 0xFF / ((1 << b) - 1) } else { raw };
+                        let alpha = if self.transparent_color == Some(TransparentColor::Gray(raw)) { 0 } else { max };
+                        result.extend_from_slice(&[v, v, v, alpha]);
+                    },
+                    GrayscaleA(_) => {
+                        let v = sample(x * 2);
+                        result.extend_from_slice(&[v, v, v, sample(x * 2 + 1)]);
+                    },
+                    Palette(_) => {
+                        let index = sample(x) as usize;
+                        let entry = self.palette.as_ref().ok_or(ApngError::NoPalette)?
+                            .get(index).ok_or(ApngError::InvalidPaletteIndex(index as u8))?;
+                        result.extend(entry.iter().map(|it| u16::from(*it)));
+                    },
+                    RGB(_) => {
+                        let (r, g, b) = (sample(x * 3), sample(x * 3 + 1), sample(x * 3 + 2));
+                        let alpha = if self.transparent_color == Some(TransparentColor::RGB(r, g, b)) { 0 } else { max };
+                        result.extend_from_slice(&[r, g, b, alpha]);
+                    },
+                    RGBA(_) =>
+                        result.extend((0 .. 4).map(|c| sample(x * 4 + c))),
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+
+/// Alpha compositing described in the APNG specification
+fn blend_over(dst: &mut [u16], src: &[u16], max: u64) {
+    let src_alpha = u64::from(src[3]);
+    if src_alpha == max {
+        dst.copy_from_slice(src);
+        return;
+    }
+    if src_alpha == 0 {
+        return;
+    }
+
+    let u = src_alpha * max;
+    let v = (max - src_alpha) * u64::from(dst[3]);
+    let alpha = u + v;
+    for c in 0 .. 3 {
+        dst[c] = ((u64::from(src[c]) * u + u64::from(dst[c]) * v + alpha / 2) / alpha) as u16;
+    }
+    dst[3] = ((alpha + max / 2) / max) as u16;
+}
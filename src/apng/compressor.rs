@@ -0,0 +1,189 @@
+
+use std::fmt;
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+use flate2::{Compress, FlushCompress, Status};
+use flate2::Compression as Level;
+use flate2::write::ZlibEncoder;
+
+use super::encoder::Compression;
+
+
+/// Backend that makes zlib streams of IDAT/fdAT chunks from filtered image data
+///
+/// Without `Encoder::set_compressor`, the encoder deflates the filtered rows by flate2 as they are made.
+/// A compressor set by `set_compressor` takes the filtered rows of the whole frame at once.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Encoder, Meta, StoredCompressor};
+///
+/// let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+/// encoder.set_compressor(Box::new(StoredCompressor));
+/// encoder.write_frame(&[0xFF, 0x00, 0x00], None, None, None).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+pub trait Compressor: fmt::Debug + Send + Sync {
+    /// Append a zlib stream of `data` to `output`.
+    /// `compression` is the level for the frame, and the compressor can ignore it.
+    fn compress(&self, data: &[u8], compression: Compression, output: &mut Vec<u8>) -> io::Result<()>;
+}
+
+/// Deflate by flate2, like the encoder without `set_compressor`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FlateCompressor;
+
+/// No compression. Output is large, but fast.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StoredCompressor;
+
+/// Exhaustive compression by Zopfli. Output is small, but very slow.
+#[cfg(feature = "zopfli")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ZopfliCompressor {
+    /// Number of iterations (Zopfli's default is 15)
+    pub iterations: u64,
+}
+
+/// Writer that deflates the written data into a zlib stream by `compress`
+pub(super) struct Deflater<'a> {
+    compress: &'a mut Compress,
+    output: &'a mut Vec<u8>,
+}
+
+/// `Compress` reused for the streams, which is made again only when the level changes
+#[derive(Debug, Default)]
+pub(super) struct ZlibStream {
+    compress: Option<(Level, Compress)>,
+}
+
+
+impl Compressor for FlateCompressor {
+    fn compress(&self, data: &[u8], compression: Compression, output: &mut Vec<u8>) -> io::Result<()> {
+        let mut e = ZlibEncoder::new(output, compression.to_level());
+        e.write_all(data)?;
+        e.finish()?;
+        Ok(())
+    }
+}
+
+impl Compressor for StoredCompressor {
+    fn compress(&self, data: &[u8], _: Compression, output: &mut Vec<u8>) -> io::Result<()> {
+        const MAX_BLOCK: usize = 0xFFFF;
+
+        // CMF (deflate, 32K window), FLG (fastest, check bits)
+        output.write_all(&[0x78, 0x01])?;
+        let mut blocks = data.chunks(MAX_BLOCK).peekable();
+        if blocks.peek().is_none() {
+            output.write_all(&[0x01, 0x00, 0x00, 0xFF, 0xFF])?;
+        }
+        while let Some(block) = blocks.next() {
+            let last = blocks.peek().is_none();
+            output.write_u8(last as u8)?;
+            output.write_u16::<LittleEndian>(block.len() as u16)?;
+            output.write_u16::<LittleEndian>(!(block.len() as u16))?;
+            output.write_all(block)?;
+        }
+        output.write_u32::<BigEndian>(adler32(data))?;
+        Ok(())
+    }
+}
+
+impl<'a> Deflater<'a> {
+    /// Free space of `output` for each step of `compress`
+    const SPACE: usize = 0x8000;
+
+    /// Start a new stream, which is appended to `output`
+    pub(super) fn new(compress: &'a mut Compress, output: &'a mut Vec<u8>) -> Self {
+        compress.reset();
+        Deflater { compress, output }
+    }
+
+    /// Write the rest of the stream
+    pub(super) fn finish(self) -> io::Result<()> {
+        loop {
+            self.output.reserve(Self::SPACE);
+            if self.compress.compress_vec(&[], self.output, FlushCompress::Finish)? == Status::StreamEnd {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<'a> Write for Deflater<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let before = self.compress.total_in();
+        loop {
+            self.output.reserve(Self::SPACE);
+            self.compress.compress_vec(buf, self.output, FlushCompress::None)?;
+            let written = (self.compress.total_in() - before) as usize;
+            if 0 < written || buf.is_empty() {
+                return Ok(written);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ZlibStream {
+    /// Start a new stream at `compression`, which is appended to `output`
+    pub(super) fn start<'a>(&'a mut self, compression: Compression, output: &'a mut Vec<u8>) -> Deflater<'a> {
+        let level = compression.to_level();
+        if self.compress.as_ref().map(|it| it.0) != Some(level) {
+            self.compress = Some((level, Compress::new(level, true)));
+        }
+        Deflater::new(self.compress_mut(), output)
+    }
+
+    /// Continue the stream of the last `start`
+    pub(super) fn resume<'a>(&'a mut self, output: &'a mut Vec<u8>) -> Deflater<'a> {
+        Deflater { compress: self.compress_mut(), output }
+    }
+
+    fn compress_mut(&mut self) -> &mut Compress {
+        &mut self.compress.as_mut().expect("Stream is not started").1
+    }
+}
+
+#[cfg(feature = "zopfli")]
+impl Default for ZopfliCompressor {
+    fn default() -> Self {
+        ZopfliCompressor { iterations: 15 }
+    }
+}
+
+#[cfg(feature = "zopfli")]
+impl Compressor for ZopfliCompressor {
+    fn compress(&self, data: &[u8], _: Compression, output: &mut Vec<u8>) -> io::Result<()> {
+        use std::num::NonZeroU64;
+
+        let iteration_count = NonZeroU64::new(self.iterations).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let options = zopfli::Options { iteration_count, ..Default::default() };
+        zopfli::compress(options, zopfli::Format::Zlib, data, output)
+    }
+}
+
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // Largest number of bytes that does not overflow before the modulo
+    const CHUNK: usize = 5552;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(CHUNK) {
+        for it in chunk {
+            a += u32::from(*it);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
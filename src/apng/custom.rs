@@ -0,0 +1,50 @@
+
+use std::io;
+
+use super::encoder::Encoder;
+use super::errors::{ApngResult, ApngError};
+
+
+impl<F: io::Write> Encoder<F> {
+    /// Write an application-specific chunk at the current position.
+    /// Calling it before the first `write_default_image` or `write_frame` puts the chunk before IDAT,
+    /// calling it between `write_frame`s puts it between the frames, and calling it before `finish` puts it after the last frame.
+    ///
+    /// The chunk type must be four ASCII letters with the case bits of a private ancillary chunk:
+    /// the first letter (ancillary) and the second letter (private) are lowercase, and the third letter (reserved) is uppercase.
+    /// The fourth letter is lowercase if the chunk is safe to copy for PNG editors that do not know it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use apng_encoder::{Color, Encoder, Meta};
+    ///
+    /// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    /// let mut buffer = vec![];
+    /// let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    /// encoder.write_custom_chunk(*b"vrSn", b"1.2.0").unwrap();
+    /// encoder.write_frame(&[0x00], None, None, None).unwrap();
+    /// encoder.write_custom_chunk(*b"hiTb", &[0, 0, 1, 1]).unwrap();
+    /// encoder.write_frame(&[0xFF], None, None, None).unwrap();
+    /// encoder.write_custom_chunk(*b"hiTb", &[0, 0, 1, 1]).unwrap();
+    /// encoder.finish().unwrap();
+    /// ```
+    pub fn write_custom_chunk(&mut self, chunk_type: [u8;4], chunk_data: &[u8]) -> ApngResult<()> {
+        validate_chunk_type(chunk_type)?;
+        self.write_chunk(chunk_type, chunk_data)
+    }
+}
+
+
+fn validate_chunk_type(chunk_type: [u8;4]) -> ApngResult<()> {
+    let is_lower = |it: u8| it & 0x20 != 0;
+    let valid =
+        chunk_type.iter().all(u8::is_ascii_alphabetic) &&
+        is_lower(chunk_type[0]) &&
+        is_lower(chunk_type[1]) &&
+        !is_lower(chunk_type[2]);
+    if !valid {
+        return Err(ApngError::InvalidChunk(String::from_utf8_lossy(&chunk_type).into_owned()));
+    }
+    Ok(())
+}
@@ -0,0 +1,338 @@
+
+use std::io::{self, Read};
+
+use byteorder::{BigEndian, ReadBytesExt};
+use flate2::Crc;
+use flate2::read::ZlibDecoder;
+
+use super::{BlendOperator, Color, Delay, DisposeOperator, Frame, Meta};
+use super::encoder::validate_color;
+use super::errors::{ApngResult, ApngError};
+use super::interlace;
+use super::metadata::Background;
+
+
+const SIGNATURE: [u8;8] = [0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a];
+
+
+/// Decoded APNG
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{decode, Color, Meta, Encoder};
+///
+/// let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = Encoder::create(&mut buffer, meta.clone()).unwrap();
+/// encoder.write_frame(&[0xFF, 0x00, 0x00], None, None, None).unwrap();
+/// encoder.finish().unwrap();
+///
+/// let decoded = decode(&mut buffer.as_slice()).unwrap();
+/// assert_eq!(decoded.meta, meta);
+/// assert_eq!(decoded.frames[0].image_data, &[0xFF, 0x00, 0x00]);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decoded {
+    /// Data of bKGD chunk
+    pub background: Option<Background>,
+    /// Image that is not a part of the animation (IDAT before the first fcTL)
+    pub default_image: Option<Vec<u8>>,
+    pub frames: Vec<DecodedFrame>,
+    /// Image data was Adam7 interlaced. `image_data` of frames are always de-interlaced.
+    pub interlaced: bool,
+    pub meta: Meta,
+    /// RGB triples in PLTE chunk
+    pub palette: Option<Vec<u8>>,
+    /// Data of tRNS chunk
+    pub transparency: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DecodedFrame {
+    /// All fields of fcTL chunk are filled
+    pub frame: Frame,
+    /// Unfiltered pixel data. Each row is packed into `ceil(width * pixel_bits / 8)` bytes.
+    pub image_data: Vec<u8>,
+}
+
+struct Chunk {
+    chunk_type: [u8;4],
+    data: Vec<u8>,
+}
+
+
+/// Decode whole APNG (or PNG) stream
+pub fn decode<R: io::Read>(reader: &mut R) -> ApngResult<Decoded> {
+    let mut signature = [0u8;8];
+    reader.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Err(ApngError::InvalidSignature);
+    }
+
+    let header = read_chunk(reader)?;
+    if &header.chunk_type != b"IHDR" {
+        return Err(invalid_chunk(b"IHDR"));
+    }
+    let (mut meta, interlaced) = read_image_header(&header)?;
+
+    let mut default_image: Option<Vec<u8>> = None;
+    let mut frames: Vec<(Frame, Vec<u8>)> = vec![];
+    let mut background = None;
+    let mut palette = None;
+    let mut transparency = None;
+    let mut sequence = 0;
+
+    loop {
+        let chunk = read_chunk(reader)?;
+        match &chunk.chunk_type {
+            b"acTL" => {
+                let mut data = checked_data(&chunk, 8)?;
+                meta.frames = data.read_u32::<BigEndian>()?;
+                meta.plays = Some(data.read_u32::<BigEndian>()?).filter(|it| *it != 0);
+            },
+            b"PLTE" =>
+                palette = Some(chunk.data),
+            b"tRNS" =>
+                transparency = Some(chunk.data),
+            b"bKGD" =>
+                background = Some(read_background(&chunk, meta.color)?),
+            b"fcTL" => {
+                check_sequence(&chunk, &mut sequence)?;
+                frames.push((read_frame_control(&chunk, &meta)?, vec![]));
+            },
+            b"IDAT" => {
+                if sequence <= 1 {
+                    if let Some((_, data)) = frames.first_mut() {
+                        data.extend_from_slice(&chunk.data);
+                        continue;
+                    }
+                }
+                if frames.is_empty() {
+                    default_image.get_or_insert_with(Vec::new).extend_from_slice(&chunk.data);
+                } else {
+                    return Err(invalid_chunk(&chunk.chunk_type));
+                }
+            },
+            b"fdAT" => {
+                check_sequence(&chunk, &mut sequence)?;
+                match frames.last_mut() {
+                    Some((_, data)) => data.extend_from_slice(&chunk.data[4..]),
+                    None => return Err(invalid_chunk(&chunk.chunk_type)),
+                }
+            },
+            b"IEND" =>
+                break,
+            chunk_type if chunk_type[0] & 0x20 == 0 =>
+                return Err(invalid_chunk(chunk_type)),
+            _ => (),
+        }
+    }
+
+    let default_image = match default_image {
+        Some(data) => Some(decompress(&data, meta.color, meta.width, meta.height, interlaced)?),
+        None => None,
+    };
+    let frames = frames.into_iter().map(|(frame, data)| {
+        let image_data = decompress(&data, meta.color, frame.width.unwrap(), frame.height.unwrap(), interlaced)?;
+        Ok(DecodedFrame { frame, image_data })
+    }).collect::<ApngResult<Vec<_>>>()?;
+
+    Ok(Decoded { background, default_image, frames, interlaced, meta, palette, transparency })
+}
+
+
+fn check_sequence(chunk: &Chunk, sequence: &mut u32) -> ApngResult<()> {
+    let actual = checked_data(chunk, 4)?.read_u32::<BigEndian>()?;
+    if actual != *sequence {
+        return Err(invalid_chunk(&chunk.chunk_type));
+    }
+    *sequence += 1;
+    Ok(())
+}
+
+fn checked_data(chunk: &Chunk, length: usize) -> ApngResult<&[u8]> {
+    if chunk.data.len() < length {
+        return Err(invalid_chunk(&chunk.chunk_type));
+    }
+    Ok(&chunk.data)
+}
+
+fn invalid_chunk(chunk_type: &[u8;4]) -> ApngError {
+    ApngError::InvalidChunk(String::from_utf8_lossy(chunk_type).into_owned())
+}
+
+fn read_chunk<R: io::Read>(reader: &mut R) -> ApngResult<Chunk> {
+    let length = reader.read_u32::<BigEndian>()?;
+    let mut chunk_type = [0u8;4];
+    reader.read_exact(&mut chunk_type)?;
+    let mut data = vec![];
+    reader.take(u64::from(length)).read_to_end(&mut data)?;
+    let expected = reader.read_u32::<BigEndian>()?;
+
+    let mut crc = Crc::new();
+    crc.update(&chunk_type);
+    crc.update(&data);
+    if data.len() != length as usize || crc.sum() != expected {
+        return Err(invalid_chunk(&chunk_type));
+    }
+
+    Ok(Chunk { chunk_type, data })
+}
+
+fn read_background(chunk: &Chunk, color: Color) -> ApngResult<Background> {
+    let background = match color {
+        Color::Grayscale(_) | Color::GrayscaleA(_) => {
+            let mut data = checked_data(chunk, 2)?;
+            Background::Gray(data.read_u16::<BigEndian>()?)
+        },
+        Color::Palette(_) =>
+            Background::PaletteIndex(checked_data(chunk, 1)?[0]),
+        Color::RGB(_) | Color::RGBA(_) => {
+            let mut data = checked_data(chunk, 6)?;
+            Background::RGB(data.read_u16::<BigEndian>()?, data.read_u16::<BigEndian>()?, data.read_u16::<BigEndian>()?)
+        },
+    };
+    Ok(background)
+}
+
+/// The frame must be in the canvas of `meta`
+fn read_frame_control(chunk: &Chunk, meta: &Meta) -> ApngResult<Frame> {
+    let mut data = &checked_data(chunk, 26)?[4..];
+    let width = data.read_u32::<BigEndian>()?;
+    let height = data.read_u32::<BigEndian>()?;
+    let x = data.read_u32::<BigEndian>()?;
+    let y = data.read_u32::<BigEndian>()?;
+    let inside = |offset: u32, size: u32, canvas: u32| u64::from(offset) + u64::from(size) <= u64::from(canvas);
+    if !inside(x, width, meta.width) || !inside(y, height, meta.height) {
+        return Err(invalid_chunk(&chunk.chunk_type));
+    }
+    let numerator = data.read_u16::<BigEndian>()?;
+    let denominator = data.read_u16::<BigEndian>()?;
+    let dispose_operator = match data.read_u8()? {
+        0 => DisposeOperator::None,
+        1 => DisposeOperator::Background,
+        2 => DisposeOperator::Previous,
+        _ => return Err(invalid_chunk(&chunk.chunk_type)),
+    };
+    let blend_operator = match data.read_u8()? {
+        0 => BlendOperator::Source,
+        1 => BlendOperator::Over,
+        _ => return Err(invalid_chunk(&chunk.chunk_type)),
+    };
+    Ok(Frame {
+        width: Some(width),
+        height: Some(height),
+        x: Some(x),
+        y: Some(y),
+        delay: Some(Delay::new(numerator, denominator)),
+        dispose_operator: Some(dispose_operator),
+        blend_operator: Some(blend_operator),
+    })
+}
+
+fn read_image_header(chunk: &Chunk) -> ApngResult<(Meta, bool)> {
+    let mut data = checked_data(chunk, 13)?;
+    let width = data.read_u32::<BigEndian>()?;
+    let height = data.read_u32::<BigEndian>()?;
+    let bit_depth = data.read_u8()?;
+    let color = match data.read_u8()? {
+        0 => Color::Grayscale(bit_depth),
+        2 => Color::RGB(bit_depth),
+        3 => Color::Palette(bit_depth),
+        4 => Color::GrayscaleA(bit_depth),
+        6 => Color::RGBA(bit_depth),
+        _ => return Err(ApngError::InvalidColor),
+    };
+    validate_color(color)?;
+    // compression_method, filter_method, interlace_method
+    let interlaced = match data {
+        [0, 0, 0] => false,
+        [0, 0, 1] => true,
+        _ => return Err(invalid_chunk(&chunk.chunk_type)),
+    };
+    Ok((Meta { color, frames: 0, height, plays: None, width }, interlaced))
+}
+
+fn decompress(compressed: &[u8], color: Color, width: u32, height: u32, interlaced: bool) -> ApngResult<Vec<u8>> {
+    let mut filtered = vec![];
+    ZlibDecoder::new(compressed).read_to_end(&mut filtered)?;
+    if !interlaced {
+        return unfilter(&filtered, color, width, height);
+    }
+
+    let mut total: usize = 0;
+    for (pass_width, pass_height) in interlace::pass_sizes(width, height).filter(|it| 0 < it.0 && 0 < it.1) {
+        total = total.checked_add(filtered_size(color, pass_width, pass_height)?).ok_or(ApngError::TooLargeImage)?;
+    }
+    if filtered.len() < total {
+        return Err(invalid_chunk(b"IDAT"));
+    }
+
+    let size = color.row_bytes(width).checked_mul(height as usize).ok_or(ApngError::TooLargeImage)?;
+    let mut result = vec![0u8; size];
+    let mut offset = 0;
+    for (index, (pass_width, pass_height)) in interlace::pass_sizes(width, height).enumerate() {
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let pass = unfilter(&filtered[offset ..], color, pass_width, pass_height)?;
+        interlace::merge(&pass, index, color, width, &mut result);
+        offset += filtered_size(color, pass_width, pass_height)?;
+    }
+    Ok(result)
+}
+
+/// Size of the filtered rows (filter type and packed samples)
+fn filtered_size(color: Color, width: u32, height: u32) -> ApngResult<usize> {
+    (color.row_bytes(width) + 1).checked_mul(height as usize).ok_or(ApngError::TooLargeImage)
+}
+
+fn unfilter(filtered: &[u8], color: Color, width: u32, height: u32) -> ApngResult<Vec<u8>> {
+    let row_bytes = color.row_bytes(width);
+    let pixel_bytes = color.pixel_bytes();
+
+    if filtered.len() < filtered_size(color, width, height)? {
+        return Err(invalid_chunk(b"IDAT"));
+    }
+    let height = height as usize;
+
+    let mut result = vec![0u8; row_bytes * height];
+    for (y, line) in filtered.chunks(row_bytes + 1).take(height).enumerate() {
+        let (previous, current) = result.split_at_mut(y * row_bytes);
+        let up = if 0 < y { &previous[(y - 1) * row_bytes ..] } else { &[][..] };
+        let current = &mut current[.. row_bytes];
+        let (filter, line) = (line[0], &line[1..]);
+
+        for i in 0 .. row_bytes {
+            let a = if pixel_bytes <= i { current[i - pixel_bytes] } else { 0 };
+            let b = up.get(i).cloned().unwrap_or(0);
+            let c = if pixel_bytes <= i { up.get(i - pixel_bytes).cloned().unwrap_or(0) } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(invalid_chunk(b"IDAT")),
+            };
+            current[i] = line[i].wrapping_add(predictor);
+        }
+    }
+
+    Ok(result)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let base = i16::from(a) + i16::from(b) - i16::from(c);
+    let pa = (base - i16::from(a)).abs();
+    let pb = (base - i16::from(b)).abs();
+    let pc = (base - i16::from(c)).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
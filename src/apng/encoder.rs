@@ -1,17 +1,26 @@
 
 use std::cmp;
-use std::io::{self, Write};
+use std::fmt;
+use std::io::{self, SeekFrom, Write};
+use std::mem;
+use std::sync::Arc;
 
 use byteorder::{BigEndian, WriteBytesExt};
 use enum_iterator::IntoEnumIterator;
-use flate2::Compression;
 use flate2::Crc;
-use flate2::write::ZlibEncoder;
+use flate2::Compression as Level;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 use super::{Color, Frame, Meta};
+use super::compressor::{Compressor, Deflater, ZlibStream};
 use super::errors::{ApngResult, ApngError};
+use super::interlace;
 
 
+/// Maximum length of chunk data in the specification
+pub(super) const MAX_CHUNK_LENGTH: usize = 0x7FFF_FFFF;
+
 /// APNG Encoder
 ///
 /// # Example
@@ -87,15 +96,73 @@ use super::errors::{ApngResult, ApngError};
 
 
 
-#[derive(Debug, Eq, PartialEq)]
-pub struct Encoder<'a, F: io::Write> {
+#[derive(Debug)]
+pub struct Encoder<F: io::Write> {
+    /// Types of the written chunks that can appear only once
+    ancillary_chunks: Vec<[u8;4]>,
+    buffers: FrameBuffers,
+    context: CompressionContext,
     default_image: bool,
-    meta: Meta,
+    frame_count: FrameCount<F>,
     sequence: u32,
-    writer: &'a mut F,
+    /// `FrameWriter` has started a frame (or the default image), and not finished it
+    unfinished_frame: bool,
+    writer: F,
     written_frames: usize,
 }
 
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EncoderOptions {
+    /// Compression level of image data. `Encoder::write_frame_with_compression` overrides it for a frame.
+    pub compression: Compression,
+    /// Used when no filter is given to `write_frame` or `write_default_image`
+    pub filter_strategy: FilterStrategy,
+    /// Write Adam7 interlaced image data for progressive display
+    pub interlaced: bool,
+    /// Maximum length of IDAT and fdAT chunk data. Larger image data is split into several chunks.
+    /// `None` for the maximum length in the specification (2^31 - 1). It must be greater than 4 for the sequence number of fdAT chunk.
+    pub max_chunk_size: Option<usize>,
+    /// Image data of the colors under 8 bits has one sample per byte, and the encoder packs them.
+    /// Otherwise, rows must be packed (`Color::row_bytes`).
+    pub unpacked_samples: bool,
+}
+
+/// What filtering and compressing image data needs, without the writer.
+/// `ParallelEncoder` sends clones of it to the worker threads.
+#[derive(Clone, Debug)]
+pub(super) struct CompressionContext {
+    /// `None` to deflate the filtered rows by flate2 as they are made
+    compressor: Option<Arc<dyn Compressor>>,
+    meta: Meta,
+    options: EncoderOptions,
+    /// Number of the palette entries
+    palette: Option<usize>,
+}
+
+/// Position of the ancillary chunks in the specification
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum ChunkPosition {
+    /// Before PLTE and image data
+    BeforePalette,
+    /// Before image data
+    BeforeImageData,
+    /// Before IEND
+    Anywhere,
+}
+
+/// zlib compression level
+///
+/// The zlib strategy (filtered, Huffman only, RLE and so on) is not configurable, because flate2 does not expose it.
+/// `Encoder::set_compressor` can replace the backend instead.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    Fast,
+    Default,
+    Best,
+    /// 0 (no compression) - 9 (best)
+    Level(u8),
+}
+
 #[derive(Clone, Copy, Debug, Eq, IntoEnumIterator, PartialEq)]
 pub enum Filter {
     None = 0,
@@ -105,74 +172,436 @@ pub enum Filter {
     Paeth = 4,
 }
 
+/// How to choose filters
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FilterStrategy {
+    /// One filter for the whole image, which gives the smallest compressed data for some sample rows
+    Infer,
+    /// A filter for each row, which gives the minimum sum of absolute differences
+    Adaptive,
+    /// A filter for each row, which gives the smallest compressed row (slow)
+    BruteForce,
+}
+
+/// How the number of frames in acTL chunk is decided
+#[derive(Debug)]
+enum FrameCount<F> {
+    /// `Meta::frames` is written at first
+    Fixed,
+    /// Chunks after IHDR are buffered until `finish`
+    Buffered(Vec<u8>),
+    /// acTL chunk at the position is rewritten in `finish`
+    Seekable(u64, Box<dyn SeekableWriter<F>>),
+}
+
+/// Seek operations on the writer of `create_seekable`, which `Encoder` can use without `F: Seek`
+trait SeekableWriter<F>: fmt::Debug + Send + Sync {
+    fn position(&self, writer: &mut F) -> io::Result<u64>;
+    /// Overwrite the bytes at `position`, and return to the end
+    fn rewrite(&self, writer: &mut F, position: u64, data: &[u8]) -> io::Result<()>;
+}
+
+#[derive(Debug)]
+struct Seeker;
+
+/// Error of `Encoder::into_inner`, which has the encoder of the unfinished image
+pub struct IntoInnerError<F: io::Write> {
+    encoder: Box<Encoder<F>>,
+    error: ApngError,
+}
+
+/// Buffers reused for each frame, so that the steady state of encoding does not allocate
+#[derive(Debug, Default)]
+pub(super) struct FrameBuffers {
+    /// Output of the compressor
+    pub(super) compressed: Vec<u8>,
+    /// Filtered rows (of all passes) for the compressor of `set_compressor`
+    pub(super) filtered: Vec<u8>,
+    pub(super) filters: FilterBuffers,
+    /// Packed rows without padding
+    pub(super) normalized: Vec<u8>,
+    /// Non-empty Adam7 passes
+    pub(super) passes: Vec<u8>,
+    /// Stream of the image data without `set_compressor`
+    pub(super) zlib: ZlibStream,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct FilterBuffers {
+    /// A filtered row for each filter of `filter_line_adaptive`
+    pub(super) candidates: Vec<Vec<u8>>,
+    /// A filtered row
+    row: Vec<u8>,
+    /// Sample rows of `infer_best_filter`
+    samples: Vec<u8>,
+    /// For the compressed sizes of `infer_best_filter` and `FilterStrategy::BruteForce`
+    pub(super) trial: Trial,
+    /// `infer_best_filter` tries the filters on the rayon thread pool.
+    /// Only the workers of `ParallelEncoder` do it, because it allocates for each frame.
+    #[cfg(feature = "parallel")]
+    parallel: bool,
+}
+
+/// Measures compressed sizes of filtered data with the reused stream
+#[derive(Debug, Default)]
+pub(super) struct Trial {
+    output: Vec<u8>,
+    zlib: ZlibStream,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Rectangle {
-    height: u32,
-    modified: bool,
-    width: u32,
+    pub(super) height: u32,
+    /// Not the whole canvas
+    pub(super) modified: bool,
+    pub(super) width: u32,
     x: u32,
     y: u32,
 }
 
 
-impl<'a, F: io::Write> Encoder<'a, F> {
-    pub fn create(writer: &'a mut F, meta: Meta) -> ApngResult<Self> {
+impl<F: io::Write + io::Seek> Encoder<F> {
+    /// Create an encoder that does not need the number of frames up front.
+    /// `Meta::frames` is ignored, and acTL chunk is rewritten in `finish`.
+    pub fn create_seekable(writer: F, meta: Meta) -> ApngResult<Self> {
+        Self::create_seekable_with_options(writer, meta, EncoderOptions::default())
+    }
+
+    pub fn create_seekable_with_options(writer: F, meta: Meta, options: EncoderOptions) -> ApngResult<Self> {
+        let mut instance = Self::new(writer, meta, options)?;
+        let position = Seeker.position(&mut instance.writer)?;
+        write_animation_control(&mut instance.writer, &instance.context.meta)?;
+        instance.frame_count = FrameCount::Seekable(position, Box::new(Seeker));
+        Ok(instance)
+    }
+}
+
+
+impl<F: io::Write> Encoder<F> {
+    pub fn create(writer: F, meta: Meta) -> ApngResult<Self> {
+        Self::create_with_options(writer, meta, EncoderOptions::default())
+    }
+
+    pub fn create_with_options(writer: F, meta: Meta, options: EncoderOptions) -> ApngResult<Self> {
+        let mut instance = Self::new(writer, meta, options)?;
+        write_animation_control(&mut instance.writer, &instance.context.meta)?;
+        Ok(instance)
+    }
+
+    /// Create an encoder that does not need the number of frames up front, for non-seekable writers.
+    /// `Meta::frames` is ignored, and all chunks after IHDR are kept in memory until `finish`.
+    pub fn create_buffered(writer: F, meta: Meta) -> ApngResult<Self> {
+        Self::create_buffered_with_options(writer, meta, EncoderOptions::default())
+    }
+
+    pub fn create_buffered_with_options(writer: F, meta: Meta, options: EncoderOptions) -> ApngResult<Self> {
+        let mut instance = Self::new(writer, meta, options)?;
+        instance.frame_count = FrameCount::Buffered(vec![]);
+        Ok(instance)
+    }
+
+    pub fn finish(self) -> ApngResult<()> {
+        self.into_inner()?;
+        Ok(())
+    }
+
+    /// Finish the stream like `finish`, and return the writer.
+    /// On failure, the error gives the encoder back (e.g. to write the missing frames) or its writer.
+    pub fn into_inner(mut self) -> Result<F, IntoInnerError<F>> {
+        match self.finish_stream() {
+            Ok(_) => Ok(self.writer),
+            Err(error) => Err(IntoInnerError { encoder: Box::new(self), error }),
+        }
+    }
+
+    /// Finish the stream like `into_inner`, and start a new image on `writer`.
+    /// Returns the writer of the finished image.
+    /// The header of the new image is written first, so the current image is left unfinished if `writer` fails.
+    ///
+    /// The options, the compressor, the way to count frames (`create_buffered` or `create_seekable`)
+    /// and the buffers for image data are kept, so encoding many images with one encoder does not allocate them again.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use apng_encoder::{Color, Encoder, Meta};
+    ///
+    /// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    /// let mut encoder = Encoder::create(vec![], meta.clone()).unwrap();
+    /// let mut images = vec![];
+    /// for value in 0 .. 4 {
+    ///     encoder.write_frame(&[value], None, None, None).unwrap();
+    ///     images.push(encoder.reset(vec![], meta.clone()).unwrap());
+    /// }
+    /// ```
+    pub fn reset(&mut self, mut writer: F, meta: Meta) -> ApngResult<F> {
+        validate_color(meta.color)?;
+        // The new image is started before the current one is finished, so that nothing fails after the writers are swapped
+        write_signature(&mut writer)?;
+        write_image_header(&mut writer, &meta, &self.context.options)?;
+        let mut position = 0;
+        match self.frame_count {
+            FrameCount::Fixed =>
+                write_animation_control(&mut writer, &meta)?,
+            FrameCount::Buffered(_) => (),
+            FrameCount::Seekable(_, ref seeker) => {
+                position = seeker.position(&mut writer)?;
+                write_animation_control(&mut writer, &meta)?;
+            },
+        }
+
+        let frame_count = self.finish_stream()?;
+        let previous = mem::replace(&mut self.writer, writer);
+        self.ancillary_chunks.clear();
+        self.default_image = false;
+        self.context.meta = meta;
+        self.context.palette = None;
+        self.sequence = 0;
+        self.written_frames = 0;
+        self.frame_count = match frame_count {
+            FrameCount::Fixed => FrameCount::Fixed,
+            FrameCount::Buffered(mut buffer) => {
+                buffer.clear();
+                FrameCount::Buffered(buffer)
+            },
+            FrameCount::Seekable(_, seeker) => FrameCount::Seekable(position, seeker),
+        };
+        Ok(previous)
+    }
+
+    /// The underlying writer. It does not have the chunks buffered by `create_buffered` until `finish`.
+    pub fn get_ref(&self) -> &F {
+        &self.writer
+    }
+
+    pub fn meta(&self) -> &Meta {
+        &self.context.meta
+    }
+
+    pub fn options(&self) -> &EncoderOptions {
+        &self.context.options
+    }
+
+    /// Replace the backend that compresses image data of the following frames (flate2 by default)
+    ///
+    /// `frame_writer` and `default_image_writer` fail after this, because they always compress by flate2.
+    pub fn set_compressor(&mut self, compressor: Box<dyn Compressor>) {
+        self.context.compressor = Some(Arc::from(compressor));
+    }
+
+    /// Returns `false` if the encoder is created by `create_buffered` or `create_seekable`
+    pub fn is_frame_count_fixed(&self) -> bool {
+        matches!(self.frame_count, FrameCount::Fixed)
+    }
+
+    pub fn write_default_image(&mut self, image_data: &[u8], filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        self.check_default_image()?;
+        self.with_buffers(|encoder, buffers| {
+            encoder.context.compress_frame_into(image_data, None, filter, row_stride, encoder.context.options.compression, buffers)?;
+            encoder.default_image = true;
+            encoder.write_image_data(*b"IDAT", &buffers.compressed)
+        })
+    }
+
+    pub fn write_frame(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        let compression = self.context.options.compression;
+        self.write_frame_with_compression(image_data, frame, filter, row_stride, compression)
+    }
+
+    /// `write_frame` at `compression` instead of `EncoderOptions::compression`
+    pub fn write_frame_with_compression(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>, compression: Compression) -> ApngResult<()> {
+        self.check_unfinished_frame()?;
+        self.check_frame_rectangle(frame)?;
+        self.with_buffers(|encoder, buffers| {
+            encoder.context.compress_frame_into(image_data, frame, filter, row_stride, compression, buffers)?;
+            encoder.write_compressed_frame(&buffers.compressed, frame)
+        })
+    }
+
+    /// Write PLTE chunk (and tRNS chunk if `transparency` is given) for `Color::Palette`.
+    ///
+    /// `palette` is a sequence of RGB triples, and `transparency` is a non-empty sequence of alpha values for the leading entries.
+    pub fn write_palette(&mut self, palette: &[u8], transparency: Option<&[u8]>) -> ApngResult<()> {
+        let bit_depth = match self.context.meta.color {
+            Color::Palette(bit_depth) => bit_depth,
+            _ => return Err(ApngError::InvalidColor),
+        };
+        if self.context.palette.is_some() {
+            return Err(ApngError::MultiPalette);
+        }
+        let entries = palette.len() / 3;
+        if entries * 3 != palette.len() || entries == 0 || (1 << bit_depth) < entries {
+            return Err(ApngError::InvalidPalette);
+        }
+        if let Some(transparency) = transparency {
+            if transparency.is_empty() || entries < transparency.len() {
+                return Err(ApngError::InvalidPalette);
+            }
+        }
+        self.context.palette = Some(entries);
+        self.write_chunk(*b"PLTE", palette)?;
+        if let Some(transparency) = transparency {
+            self.write_chunk(*b"tRNS", transparency)?;
+        }
+        Ok(())
+    }
+
+    fn new(writer: F, meta: Meta, options: EncoderOptions) -> ApngResult<Self> {
         validate_color(meta.color)?;
+        options.compression.validate()?;
+        if let Some(size) = options.max_chunk_size {
+            if size <= 4 || MAX_CHUNK_LENGTH < size {
+                return Err(ApngError::InvalidArgument);
+            }
+        }
         let mut instance = Encoder {
+            ancillary_chunks: vec![],
+            buffers: FrameBuffers::default(),
+            context: CompressionContext { compressor: None, meta, options, palette: None },
             default_image: false,
-            meta,
+            frame_count: FrameCount::Fixed,
             sequence: 0,
+            unfinished_frame: false,
             writer,
             written_frames: 0,
         };
-        Self::write_signature(&mut instance)?;
-        Self::write_image_header(&mut instance)?;
-        Self::write_animation_control(&mut instance)?;
+        write_signature(&mut instance.writer)?;
+        write_image_header(&mut instance.writer, &instance.context.meta, &instance.context.options)?;
         Ok(instance)
     }
 
-    pub fn finish(mut self) -> ApngResult<()> {
-        if self.written_frames < self.meta.frames as usize {
-            return Err(ApngError::NotEnoughFrames(self.meta.frames as usize, self.written_frames));
+    fn fix_frames(&mut self) -> ApngResult<()> {
+        if self.written_frames == 0 {
+            return Err(ApngError::NotEnoughFrames(1, 0));
+        }
+        self.context.meta.frames = self.written_frames as u32;
+        Ok(())
+    }
+
+    /// Write the rest of the stream, and return how the number of frames was decided.
+    /// The encoder is left as `FrameCount::Fixed`.
+    fn finish_stream(&mut self) -> ApngResult<FrameCount<F>> {
+        self.check_unfinished_frame()?;
+        if self.is_frame_count_fixed() {
+            if self.written_frames < self.context.meta.frames as usize {
+                return Err(ApngError::NotEnoughFrames(self.context.meta.frames as usize, self.written_frames));
+            }
+        } else {
+            self.fix_frames()?;
+        }
+        let frame_count = mem::replace(&mut self.frame_count, FrameCount::Fixed);
+        match frame_count {
+            FrameCount::Fixed => (),
+            FrameCount::Buffered(ref buffer) => {
+                write_animation_control(&mut self.writer, &self.context.meta)?;
+                self.writer.write_all(buffer)?;
+            },
+            FrameCount::Seekable(position, ref seeker) => {
+                let mut chunk = vec![];
+                write_animation_control(&mut chunk, &self.context.meta)?;
+                seeker.rewrite(&mut self.writer, position, &chunk)?;
+            },
         }
         let zero: [u8;0] = [];
-        self.write_chunk(*b"IEND", &zero)
+        self.write_chunk(*b"IEND", &zero)?;
+        Ok(frame_count)
     }
 
-    pub fn write_default_image(&mut self, image_data: &[u8], filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+    /// Run `f` with the buffers of the encoder, which are taken out during `f` to borrow the encoder mutably
+    fn with_buffers<T, G>(&mut self, f: G) -> ApngResult<T>
+    where G: FnOnce(&mut Self, &mut FrameBuffers) -> ApngResult<T> {
+        let mut buffers = mem::take(&mut self.buffers);
+        let result = f(self, &mut buffers);
+        self.buffers = buffers;
+        result
+    }
+
+    /// Write the frame compressed by `compress_frame`
+    pub(super) fn write_compressed_frame(&mut self, compressed: &[u8], frame: Option<&Frame>) -> ApngResult<()> {
+        let chunk_type = self.start_frame(frame)?;
+        self.write_image_data(chunk_type, compressed)
+    }
+
+    /// Mark the default image as written, and return the chunk type of its image data
+    pub(super) fn start_default_image(&mut self) -> ApngResult<[u8;4]> {
+        self.check_default_image()?;
+        self.default_image = true;
+        Ok(*b"IDAT")
+    }
+
+    /// Count the frame and write fcTL chunk, and return the chunk type of its image data.
+    /// The frame is checked before anything is changed, so a rejected frame is not counted.
+    pub(super) fn start_frame(&mut self, frame: Option<&Frame>) -> ApngResult<[u8;4]> {
+        self.check_unfinished_frame()?;
+        self.check_frame_count(1)?;
+        self.check_frame_rectangle(frame)?;
+        let chunk_type = if !self.default_image && self.sequence == 0 { *b"IDAT" } else { *b"fdAT" };
+        self.write_frame_control(frame)?;
+        self.written_frames += 1;
+        Ok(chunk_type)
+    }
+
+    /// Write IDAT chunks, or fdAT chunks with the next sequence numbers.
+    /// The data is split by `image_data_chunk_size`.
+    pub(super) fn write_image_data(&mut self, chunk_type: [u8;4], compressed: &[u8]) -> ApngResult<()> {
+        let chunk_size = self.image_data_chunk_size(chunk_type);
+        for part in compressed.chunks(chunk_size) {
+            if chunk_type == *b"IDAT" {
+                self.write_chunk(chunk_type, part)?;
+            } else {
+                let sequence = self.next_sequence().to_be_bytes();
+                self.write_chunk_parts(chunk_type, &[&sequence, part])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Maximum length of the compressed data in a IDAT or fdAT chunk
+    pub(super) fn image_data_chunk_size(&self, chunk_type: [u8;4]) -> usize {
+        let size = self.context.options.max_chunk_size.unwrap_or(MAX_CHUNK_LENGTH);
+        if chunk_type == *b"IDAT" { size } else { size - 4 }
+    }
+
+    fn check_default_image(&self) -> ApngResult<()> {
+        self.check_unfinished_frame()?;
+        self.context.check_palette()?;
         if self.default_image {
             return Err(ApngError::MulitiDefaultImage);
         }
         if 0 < self.sequence {
             return Err(ApngError::DefaultImageNotAtFirst);
         }
-        self.default_image = true;
-        let rect = self.compute_rect(None);
-        let mut buffer = vec![];
-        self.make_image_data(image_data, row_stride, &mut buffer, rect, filter)?;
-        self.write_chunk(*b"IDAT", &buffer)?;
         Ok(())
     }
 
-    pub fn write_frame(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
-        self.written_frames += 1;
-        if (self.meta.frames as usize) < self.written_frames {
-            return Err(ApngError::TooManyFrames(self.meta.frames as usize, self.written_frames));
+    fn check_unfinished_frame(&self) -> ApngResult<()> {
+        if self.unfinished_frame {
+            return Err(ApngError::UnfinishedFrame);
         }
-        if !self.default_image && self.sequence == 0 {
-            self.write_animation_frame_with_default(image_data, row_stride, frame, filter)
-        } else {
-            self.write_animation_frame(image_data, row_stride, frame, filter)
+        Ok(())
+    }
+
+    /// Mark the frame started by `start_frame` (or `start_default_image`) as being written by `FrameWriter`
+    pub(super) fn set_unfinished_frame(&mut self, unfinished: bool) {
+        self.unfinished_frame = unfinished;
+    }
+
+    /// Fails if `count` more frames exceed `Meta::frames` of `create`
+    pub(super) fn check_frame_count(&self, count: usize) -> ApngResult<()> {
+        let frames = self.written_frames + count;
+        if matches!(self.frame_count, FrameCount::Fixed) && (self.context.meta.frames as usize) < frames {
+            return Err(ApngError::TooManyFrames(self.context.meta.frames as usize, frames));
         }
+        Ok(())
     }
 
-    fn compute_rect(&self, frame: Option<&Frame>) -> Rectangle {
-        let width = frame.and_then(|it| it.width).unwrap_or(self.meta.width);
-        let height = frame.and_then(|it| it.height).unwrap_or(self.meta.height);
-        let x = frame.and_then(|it| it.x).unwrap_or(0);
-        let y = frame.and_then(|it| it.y).unwrap_or(0);
-        let modified = x != 0 || y != 0 || width != self.meta.width || height != self.meta.height;
-        Rectangle { width, height, x, y, modified }
+    /// Fails if the frame is outside the canvas.
+    /// The first frame is the default image too, unless `write_default_image` is called, so it must cover the canvas.
+    pub(super) fn check_frame_rectangle(&self, frame: Option<&Frame>) -> ApngResult<()> {
+        if !self.default_image && self.sequence == 0 && Rectangle::of_frame(&self.context.meta, frame).modified {
+            return Err(ApngError::InvalidDefaultImageRectangle);
+        }
+        self.context.compute_rect(frame).map(|_| ())
     }
 
     fn next_sequence(&mut self) -> u32 {
@@ -181,117 +610,313 @@ impl<'a, F: io::Write> Encoder<'a, F> {
         result
     }
 
-    fn make_image_data(&mut self, image_data: &[u8], row_stride: Option<usize>, buffer: &mut Vec<u8>, rect: Rectangle, filter: Option<Filter>) -> ApngResult<()> {
-        let row_stride = self.compute_row_stride(&image_data, row_stride, rect)?;
-        let mut e = ZlibEncoder::new(buffer, Compression::best());
-        let pixel_bytes = self.meta.color.pixel_bytes();
-        let filter = filter.map(Ok).unwrap_or_else(|| infer_best_filter(image_data, row_stride, pixel_bytes))?;
-        filter.apply(image_data, row_stride, pixel_bytes, &mut e)?;
-        e.finish()?;
-        Ok(())
+    /// The underlying writer to clear the sent chunks
+    #[cfg(feature = "async")]
+    pub(super) fn get_mut(&mut self) -> &mut F {
+        &mut self.writer
     }
 
-    fn compute_row_stride(&self, image_data: &[u8], row_stride: Option<usize>, rect: Rectangle) -> ApngResult<usize> {
-        let row_stride = row_stride.unwrap_or_else(|| rect.width as usize * self.meta.color.pixel_bytes());
-        let data_height = (image_data.len() / row_stride) as u32;
-        if self.meta.width < rect.right() || self.meta.height < rect.bottom() || rect.bottom() < data_height{
-            return Err(ApngError::TooLargeImage);
-        }
-        if data_height < rect.height {
-            return Err(ApngError::TooSmallImage);
-        }
-        Ok(row_stride)
+    pub(super) fn context(&self) -> &CompressionContext {
+        &self.context
     }
 
-    fn write_animation_frame(&mut self, image_data: &[u8], row_stride: Option<usize>, frame: Option<&Frame>, filter: Option<Filter>) -> ApngResult<()> {
-        let rect = self.write_frame_control(frame)?;
-        let mut buffer = vec![];
-        buffer.write_u32::<BigEndian>(self.next_sequence())?;
-        self.make_image_data(image_data, row_stride, &mut buffer, rect, filter)?;
-        self.write_chunk(*b"fdAT", &buffer)?;
-        Ok(())
+    /// Buffers for `FrameWriter`, which takes some of them out until `FrameWriter::finish`
+    pub(super) fn buffers_mut(&mut self) -> &mut FrameBuffers {
+        &mut self.buffers
     }
 
-    fn write_animation_frame_with_default(&mut self, image_data: &[u8], row_stride: Option<usize>, frame: Option<&Frame>, filter: Option<Filter>) -> ApngResult<()> {
-        let rect = self.write_frame_control(frame)?;
-        if rect.modified {
-            return Err(ApngError::InvalidDefaultImageRectangle);
+    pub(super) fn has_written_chunk(&self, chunk_type: [u8;4]) -> bool {
+        self.ancillary_chunks.contains(&chunk_type)
+    }
+
+    /// Number of the palette entries written by `write_palette`
+    pub(super) fn palette_entries(&self) -> Option<usize> {
+        self.context.palette
+    }
+
+    /// Write the ancillary chunk that can appear only once, and must be written before `position`
+    pub(super) fn write_unique_chunk(&mut self, chunk_type: [u8;4], chunk_data: &[u8], position: ChunkPosition) -> ApngResult<()> {
+        let name = || String::from_utf8_lossy(&chunk_type).into_owned();
+        if self.has_written_chunk(chunk_type) {
+            return Err(ApngError::MultiChunk(name()));
         }
-        let mut buffer = vec![];
-        self.make_image_data(image_data, row_stride, &mut buffer, rect, filter)?;
-        self.write_chunk(*b"IDAT", &buffer)?;
-        Ok(())
+        let image_data = self.default_image || 0 < self.written_frames;
+        let misplaced = match position {
+            ChunkPosition::BeforePalette => image_data || self.context.palette.is_some(),
+            ChunkPosition::BeforeImageData => image_data,
+            ChunkPosition::Anywhere => false,
+        };
+        if misplaced {
+            return Err(ApngError::InvalidChunkPosition(name()));
+        }
+        self.ancillary_chunks.push(chunk_type);
+        self.write_chunk(chunk_type, chunk_data)
     }
 
-    fn write_animation_control(&mut self) -> ApngResult<()> {
-        let mut buffer = vec![];
-        buffer.write_u32::<BigEndian>(self.meta.frames)?;
-        buffer.write_u32::<BigEndian>(self.meta.plays.unwrap_or(0))?;
-        self.write_chunk(*b"acTL", &buffer)
-    }
-
-    fn write_chunk(&mut self, chunk_type: [u8;4], chunk_data: &[u8]) -> ApngResult<()> {
-        // Length
-        self.writer.write_u32::<BigEndian>(chunk_data.len() as u32)?;
-        // Type
-        self.writer.write_all(&chunk_type)?;
-        // Data
-        self.writer.write_all(chunk_data)?;
-        // CRC
-        let mut crc = Crc::new();
-        crc.update(&chunk_type);
-        crc.update(chunk_data);
-        self.writer.write_u32::<BigEndian>(crc.sum() as u32)?;
-        Ok(())
+    pub(super) fn write_chunk(&mut self, chunk_type: [u8;4], chunk_data: &[u8]) -> ApngResult<()> {
+        self.write_chunk_parts(chunk_type, &[chunk_data])
+    }
+
+    fn write_chunk_parts(&mut self, chunk_type: [u8;4], parts: &[&[u8]]) -> ApngResult<()> {
+        if let FrameCount::Buffered(ref mut buffer) = self.frame_count {
+            return write_chunk_parts(buffer, chunk_type, parts);
+        }
+        write_chunk_parts(&mut self.writer, chunk_type, parts)
     }
 
     fn write_frame_control(&mut self, frame: Option<&Frame>) -> ApngResult<Rectangle> {
-        let rect = self.compute_rect(frame);
+        let rect = self.context.compute_rect(frame)?;
         let delay = frame.and_then(|it| it.delay).unwrap_or_default();
         let dispose = frame.and_then(|it| it.dispose_operator).unwrap_or_default() as u8;
         let blend = frame.and_then(|it| it.blend_operator).unwrap_or_default() as u8;
 
-        let mut buffer = vec![];
-        buffer.write_u32::<BigEndian>(self.next_sequence())?;
-        buffer.write_u32::<BigEndian>(rect.width)?;
-        buffer.write_u32::<BigEndian>(rect.height)?;
-        buffer.write_u32::<BigEndian>(rect.x)?;
-        buffer.write_u32::<BigEndian>(rect.y)?;
-        buffer.write_u16::<BigEndian>(delay.numerator)?;
-        buffer.write_u16::<BigEndian>(delay.denominator)?;
-        buffer.write_all(&[dispose, blend])?;
+        let mut buffer = [0u8; 26];
+        let mut cursor = &mut buffer[..];
+        cursor.write_u32::<BigEndian>(self.next_sequence())?;
+        cursor.write_u32::<BigEndian>(rect.width)?;
+        cursor.write_u32::<BigEndian>(rect.height)?;
+        cursor.write_u32::<BigEndian>(rect.x)?;
+        cursor.write_u32::<BigEndian>(rect.y)?;
+        cursor.write_u16::<BigEndian>(delay.numerator)?;
+        cursor.write_u16::<BigEndian>(delay.denominator)?;
+        cursor.write_all(&[dispose, blend])?;
         self.write_chunk(*b"fcTL", &buffer)?;
 
         Ok(rect)
     }
+}
+
+
+impl CompressionContext {
+    /// Filter and compress image data of the frame (or the default image for `None`) without writing anything
+    #[cfg(feature = "parallel")]
+    pub(super) fn compress_frame(&self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<Vec<u8>> {
+        let mut buffers = FrameBuffers::default();
+        buffers.filters.parallel = true;
+        self.compress_frame_into(image_data, frame, filter, row_stride, self.options.compression, &mut buffers)?;
+        Ok(buffers.compressed)
+    }
+
+    /// Filter and compress image data into `buffers.compressed` like `compress_frame`
+    fn compress_frame_into(&self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>, compression: Compression, buffers: &mut FrameBuffers) -> ApngResult<()> {
+        self.check_palette()?;
+        compression.validate()?;
+        let rect = self.compute_rect(frame)?;
+        self.make_image_data(image_data, row_stride, buffers, rect, filter, compression)
+    }
+
+    /// `true` if `Encoder::set_compressor` replaced flate2
+    pub(super) fn has_compressor(&self) -> bool {
+        self.compressor.is_some()
+    }
+
+    pub(super) fn check_palette(&self) -> ApngResult<()> {
+        if let Color::Palette(_) = self.meta.color {
+            if self.palette.is_none() {
+                return Err(ApngError::NoPalette);
+            }
+        }
+        Ok(())
+    }
+
+    /// The rectangle of the frame, which must be in the canvas and not be empty
+    pub(super) fn compute_rect(&self, frame: Option<&Frame>) -> ApngResult<Rectangle> {
+        let rect = Rectangle::of_frame(&self.meta, frame);
+        rect.validate(&self.meta)?;
+        Ok(rect)
+    }
+
+    /// Make the compressed image data in `buffers.compressed`
+    fn make_image_data(&self, image_data: &[u8], row_stride: Option<usize>, buffers: &mut FrameBuffers, rect: Rectangle, filter: Option<Filter>, compression: Compression) -> ApngResult<()> {
+        let FrameBuffers { compressed, filtered, filters, normalized, passes, zlib } = buffers;
+        let color = self.meta.color;
+        let image_data = self.normalize_image_data(image_data, row_stride, rect, normalized)?;
+        self.validate_palette_indices(image_data, color.row_bytes(rect.width), rect)?;
+        let image_data = if self.options.interlaced {
+            passes.clear();
+            interlace::split(image_data, color, rect.width, rect.height, passes);
+            passes.as_slice()
+        } else {
+            image_data
+        };
+        compressed.clear();
+        match self.compressor {
+            None => {
+                let mut e = zlib.start(compression, compressed);
+                self.filter_frame(image_data, rect, filter, compression, filters, &mut e)?;
+                e.finish()?;
+            },
+            Some(ref compressor) => {
+                filtered.clear();
+                filtered.reserve((color.row_bytes(rect.width) + 1) * rect.height as usize);
+                self.filter_frame(image_data, rect, filter, compression, filters, filtered)?;
+                compressor.compress(filtered, compression, compressed)?;
+            },
+        }
+        Ok(())
+    }
 
-    fn write_image_header(&mut self) -> ApngResult<()> {
-        use super::Color::*;
+    /// Filter the packed rows (or the Adam7 passes split by `interlace::split`)
+    fn filter_frame<E: Write>(&self, image_data: &[u8], rect: Rectangle, filter: Option<Filter>, compression: Compression, buffers: &mut FilterBuffers, e: &mut E) -> ApngResult<()> {
+        let color = self.meta.color;
+        if !self.options.interlaced {
+            return self.filter_image_data(image_data, color.row_bytes(rect.width), filter, compression, buffers, e);
+        }
+        let mut rest = image_data;
+        for (width, height) in interlace::pass_sizes(rect.width, rect.height).filter(|it| 0 < it.0 && 0 < it.1) {
+            let (pass, next) = rest.split_at(color.row_bytes(width) * height as usize);
+            self.filter_image_data(pass, color.row_bytes(width), filter, compression, buffers, e)?;
+            rest = next;
+        }
+        Ok(())
+    }
 
-        let mut buffer = vec![];
-        buffer.write_u32::<BigEndian>(self.meta.width)?;
-        buffer.write_u32::<BigEndian>(self.meta.height)?;
-        // Alpha - Color - Palette
-        let color_type = match self.meta.color {
-            Grayscale(_) => 0b000,
-            GrayscaleA(_) => 0b100,
-            RGB(_) => 0b010,
-            RGBA(_) => 0b110,
+    fn filter_image_data<E: Write>(&self, image_data: &[u8], row_stride: usize, filter: Option<Filter>, compression: Compression, buffers: &mut FilterBuffers, e: &mut E) -> ApngResult<()> {
+        let pixel_bytes = self.meta.color.pixel_bytes();
+        match (filter, self.options.filter_strategy) {
+            (Some(filter), _) =>
+                filter.apply(image_data, row_stride, pixel_bytes, &mut buffers.row, e),
+            (None, FilterStrategy::Infer) =>
+                infer_best_filter(image_data, row_stride, pixel_bytes, compression, buffers)?.apply(image_data, row_stride, pixel_bytes, &mut buffers.row, e),
+            (None, FilterStrategy::Adaptive) =>
+                filter_adaptive(image_data, row_stride, pixel_bytes, None, &mut buffers.candidates, e),
+            (None, FilterStrategy::BruteForce) =>
+                filter_adaptive(image_data, row_stride, pixel_bytes, Some((compression, &mut buffers.trial)), &mut buffers.candidates, e),
+        }
+    }
+
+    /// Make packed rows without padding. `image_data` is returned as it is if it has no padding,
+    /// otherwise the rows are made in `normalized`.
+    fn normalize_image_data<'b>(&self, image_data: &'b [u8], row_stride: Option<usize>, rect: Rectangle, normalized: &'b mut Vec<u8>) -> ApngResult<&'b [u8]> {
+        let color = self.meta.color;
+        let unpacked = self.options.unpacked_samples && color.pixel_bits() < 8;
+        let row_bytes = if unpacked { rect.width as usize } else { color.row_bytes(rect.width) };
+        let row_stride = row_stride.unwrap_or(row_bytes);
+        if row_stride < row_bytes {
+            return Err(ApngError::InvalidArgument);
+        }
+        let row_stride = self.compute_row_stride(image_data, row_bytes, row_stride, rect)?;
+
+        let height = rect.height as usize;
+        if !unpacked && row_stride == row_bytes {
+            return Ok(&image_data[.. row_bytes * height]);
+        }
+
+        normalized.clear();
+        normalized.reserve(color.row_bytes(rect.width) * height);
+        for line in image_data.chunks(row_stride).take(height) {
+            if unpacked {
+                pack_samples(&line[.. row_bytes], color, normalized)?;
+            } else {
+                normalized.extend_from_slice(&line[.. row_bytes]);
+            }
+        }
+        Ok(normalized)
+    }
+
+    fn compute_row_stride(&self, image_data: &[u8], row_bytes: usize, row_stride: usize, rect: Rectangle) -> ApngResult<usize> {
+        // The last row does not need the padding
+        let data_height = match image_data.len().checked_sub(row_bytes) {
+            Some(rest) => (rest / row_stride + 1) as u32,
+            None => 0,
         };
-        // ... compression_method, filter_method, interlace_method
-        buffer.write_all(&[self.meta.color.bit_depth(), color_type, 0, 0, 0])?;
-        self.write_chunk(*b"IHDR", &buffer)
+        if rect.bottom()? < data_height {
+            return Err(ApngError::TooLargeImage);
+        }
+        if data_height < rect.height {
+            return Err(ApngError::TooSmallImage);
+        }
+        Ok(row_stride)
     }
 
-    fn write_signature(&mut self) -> ApngResult<()> {
-        self.writer.write_all(&[0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a])?;
+    pub(super) fn validate_palette_indices(&self, image_data: &[u8], row_stride: usize, rect: Rectangle) -> ApngResult<()> {
+        if let Some(entries) = self.palette {
+            let bit_depth = self.meta.color.bit_depth();
+            for line in image_data.chunks(row_stride) {
+                let mut indices = (0 .. rect.width as usize).map(|x| get_sample(line, x, bit_depth));
+                if let Some(index) = indices.find(|it| entries <= *it as usize) {
+                    return Err(ApngError::InvalidPaletteIndex(index));
+                }
+            }
+        }
         Ok(())
     }
 }
 
 
+/// The compressor and the buffers for image data are not compared
+impl<F: io::Write + PartialEq> PartialEq for Encoder<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ancillary_chunks == other.ancillary_chunks &&
+            self.default_image == other.default_image &&
+            self.context == other.context &&
+            self.frame_count == other.frame_count &&
+            self.sequence == other.sequence &&
+            self.unfinished_frame == other.unfinished_frame &&
+            self.writer == other.writer &&
+            self.written_frames == other.written_frames
+    }
+}
+
+impl<F: io::Write + Eq> Eq for Encoder<F> {}
+
+/// The compressor is not compared
+impl PartialEq for CompressionContext {
+    fn eq(&self, other: &Self) -> bool {
+        self.meta == other.meta &&
+            self.options == other.options &&
+            self.palette == other.palette
+    }
+}
+
+
+impl<F> PartialEq for FrameCount<F> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FrameCount::Fixed, FrameCount::Fixed) => true,
+            (FrameCount::Buffered(a), FrameCount::Buffered(b)) => a == b,
+            (FrameCount::Seekable(a, _), FrameCount::Seekable(b, _)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Best
+    }
+}
+
+impl Compression {
+    pub(super) fn to_level(self) -> Level {
+        match self {
+            Compression::Fast => Level::fast(),
+            Compression::Default => Level::default(),
+            Compression::Best => Level::best(),
+            Compression::Level(level) => Level::new(u32::from(level)),
+        }
+    }
+
+    pub(super) fn validate(self) -> ApngResult<()> {
+        match self {
+            Compression::Level(level) if 9 < level => Err(ApngError::InvalidArgument),
+            _ => Ok(()),
+        }
+    }
+}
+
+
+impl Default for FilterStrategy {
+    fn default() -> Self {
+        FilterStrategy::Infer
+    }
+}
+
+
 impl Filter {
-    fn apply<E: Write>(self, image_data: &[u8], row_stride: usize, pixel_bytes: usize, e: &mut E) -> ApngResult<()> {
+    /// `buffer` is used for a filtered row
+    fn apply<E: Write>(self, image_data: &[u8], row_stride: usize, pixel_bytes: usize, buffer: &mut Vec<u8>, e: &mut E) -> ApngResult<()> {
         let f = match self {
             Filter::Average => filter_average,
             Filter::None => filter_none,
@@ -299,23 +924,131 @@ impl Filter {
             Filter::Sub => filter_sub,
             Filter::Up => filter_up,
         };
-        f(image_data, row_stride, pixel_bytes, e)
+        f(image_data, row_stride, pixel_bytes, buffer, e)
+    }
+}
+
+
+impl Trial {
+    /// Size of the zlib stream of the data written by `f`
+    pub(super) fn compressed_size<G>(&mut self, compression: Compression, f: G) -> ApngResult<usize>
+    where G: FnOnce(&mut Deflater) -> ApngResult<()> {
+        self.output.clear();
+        let mut e = self.zlib.start(compression, &mut self.output);
+        f(&mut e)?;
+        e.finish()?;
+        Ok(self.output.len())
+    }
+}
+
+
+impl<F: io::Write> IntoInnerError<F> {
+    pub fn error(&self) -> &ApngError {
+        &self.error
+    }
+
+    /// The encoder to fix the error and `into_inner` again
+    pub fn into_encoder(self) -> Encoder<F> {
+        *self.encoder
+    }
+
+    /// The writer of the unfinished image
+    pub fn into_writer(self) -> F {
+        self.encoder.writer
+    }
+}
+
+/// The encoder is not shown, so that `F` does not need `Debug`
+impl<F: io::Write> fmt::Debug for IntoInnerError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IntoInnerError").field("error", &self.error).finish()
+    }
+}
+
+impl<F: io::Write> fmt::Display for IntoInnerError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<F: io::Write> From<IntoInnerError<F>> for ApngError {
+    fn from(error: IntoInnerError<F>) -> ApngError {
+        error.error
+    }
+}
+
+
+impl<F: io::Write + io::Seek> SeekableWriter<F> for Seeker {
+    fn position(&self, writer: &mut F) -> io::Result<u64> {
+        writer.seek(SeekFrom::Current(0))
+    }
+
+    fn rewrite(&self, writer: &mut F, position: u64, data: &[u8]) -> io::Result<()> {
+        let end = writer.seek(SeekFrom::Current(0))?;
+        writer.seek(SeekFrom::Start(position))?;
+        writer.write_all(data)?;
+        writer.seek(SeekFrom::Start(end))?;
+        Ok(())
     }
 }
 
 
 impl Rectangle {
-    fn right(&self) -> u32 {
-        self.x + self.width
+    /// The rectangle of the frame (or the whole canvas for `None`) without validation
+    pub(super) fn of_frame(meta: &Meta, frame: Option<&Frame>) -> Self {
+        let width = frame.and_then(|it| it.width).unwrap_or(meta.width);
+        let height = frame.and_then(|it| it.height).unwrap_or(meta.height);
+        let x = frame.and_then(|it| it.x).unwrap_or(0);
+        let y = frame.and_then(|it| it.y).unwrap_or(0);
+        let modified = x != 0 || y != 0 || width != meta.width || height != meta.height;
+        Rectangle { width, height, x, y, modified }
     }
 
-    fn bottom(&self) -> u32 {
-        self.y + self.height
+    /// Fails if the rectangle is empty or outside the canvas
+    pub(super) fn validate(&self, meta: &Meta) -> ApngResult<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(ApngError::TooSmallImage);
+        }
+        if meta.width < self.right()? || meta.height < self.bottom()? {
+            return Err(ApngError::TooLargeImage);
+        }
+        Ok(())
+    }
+
+    pub(super) fn right(&self) -> ApngResult<u32> {
+        self.x.checked_add(self.width).ok_or(ApngError::TooLargeImage)
+    }
+
+    pub(super) fn bottom(&self) -> ApngResult<u32> {
+        self.y.checked_add(self.height).ok_or(ApngError::TooLargeImage)
     }
 }
 
 
-fn filter_none<E: Write>(image_data: &[u8], row_stride: usize, _pixel_bytes: usize, e: &mut E) -> ApngResult<()> {
+/// Encode the frames (image data and optional frame control) into APNG bytes.
+/// `Meta::frames` is ignored, and decided by the number of the frames.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{encode_to_vec, Color, Meta};
+///
+/// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 0, plays: None };
+/// let frames: [(&[u8], _); 2] = [(&[0x00], None), (&[0xFF], None)];
+/// let png = encode_to_vec(meta, frames.iter().cloned()).unwrap();
+/// assert_eq!(&png[1 .. 4], b"PNG");
+/// ```
+pub fn encode_to_vec<'a, I>(meta: Meta, frames: I) -> ApngResult<Vec<u8>>
+where I: IntoIterator<Item = (&'a [u8], Option<&'a Frame>)> {
+    let mut encoder = Encoder::create_seekable(io::Cursor::new(vec![]), meta)?;
+    for (image_data, frame) in frames {
+        encoder.write_frame(image_data, frame, None, None)?;
+    }
+    Ok(encoder.into_inner()?.into_inner())
+}
+
+
+fn filter_none<E: Write>(image_data: &[u8], row_stride: usize, _pixel_bytes: usize, _buffer: &mut Vec<u8>, e: &mut E) -> ApngResult<()> {
     for line in image_data.chunks(row_stride) {
         e.write_all(&[0x00])?;
         e.write_all(line)?;
@@ -323,8 +1056,8 @@ fn filter_none<E: Write>(image_data: &[u8], row_stride: usize, _pixel_bytes: usi
     Ok(())
 }
 
-fn filter_sub<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, e: &mut E) -> ApngResult<()> {
-    let mut buffer = vec![0; row_stride];
+fn filter_sub<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, buffer: &mut Vec<u8>, e: &mut E) -> ApngResult<()> {
+    buffer.resize(row_stride, 0);
 
     for line in image_data.chunks(row_stride) {
         e.write_all(&[0x01])?;
@@ -332,110 +1065,179 @@ fn filter_sub<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize
         for (i, it) in buffer.iter_mut().enumerate().take(row_stride).skip(pixel_bytes) {
             *it = line[i].wrapping_sub(line[i - pixel_bytes]);
         }
-        e.write_all(&buffer)?;
+        e.write_all(buffer)?;
     }
 
     Ok(())
 }
 
-fn filter_up<E: Write>(image_data: &[u8], row_stride: usize, _pixel_bytes: usize, e: &mut E) -> ApngResult<()> {
-    let lines: Vec<&[u8]> = image_data.chunks(row_stride).collect();
-    let mut buffer = vec![0; row_stride];
+fn filter_up<E: Write>(image_data: &[u8], row_stride: usize, _pixel_bytes: usize, buffer: &mut Vec<u8>, e: &mut E) -> ApngResult<()> {
+    buffer.resize(row_stride, 0);
 
     e.write_all(&[0x02])?;
-    e.write_all(&lines[0])?;
+    e.write_all(&image_data[.. row_stride])?;
 
-    for line in lines.windows(2) {
+    for (previous, line) in row_pairs(image_data, row_stride) {
         e.write_all(&[0x02])?;
         for (i, it) in buffer.iter_mut().enumerate().take(row_stride) {
-            *it = line[1][i].wrapping_sub(line[0][i]);
+            *it = line[i].wrapping_sub(previous[i]);
         }
-        e.write_all(&buffer)?;
+        e.write_all(buffer)?;
     }
 
     Ok(())
 }
 
-fn filter_average<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, e: &mut E) -> ApngResult<()> {
-    let lines: Vec<&[u8]> = image_data.chunks(row_stride).collect();
-    let mut buffer = vec![0; row_stride];
+fn filter_average<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, buffer: &mut Vec<u8>, e: &mut E) -> ApngResult<()> {
+    let first = &image_data[.. row_stride];
+    buffer.resize(row_stride, 0);
 
     e.write_all(&[0x03])?;
-    buffer[..pixel_bytes].clone_from_slice(&lines[0][..pixel_bytes]);
+    buffer[..pixel_bytes].clone_from_slice(&first[..pixel_bytes]);
     for (i, it) in buffer.iter_mut().enumerate().take(row_stride).skip(pixel_bytes) {
-        *it = lines[0][i].wrapping_sub(lines[0][i - pixel_bytes] / 2);
+        *it = first[i].wrapping_sub(first[i - pixel_bytes] / 2);
     }
-    e.write_all(&buffer)?;
+    e.write_all(buffer)?;
 
-    for line in lines.windows(2) {
+    for (previous, line) in row_pairs(image_data, row_stride) {
         e.write_all(&[0x03])?;
         for (i, it) in buffer.iter_mut().enumerate().take(pixel_bytes) {
-            *it = line[1][i].wrapping_sub(line[0][i] / 2);
+            *it = line[i].wrapping_sub(previous[i] / 2);
         }
         for (i, it) in buffer.iter_mut().enumerate().take(row_stride).skip(pixel_bytes) {
-            let sum = (i16::from(line[1][i - pixel_bytes]) + i16::from(line[0][i])) / 2;
-            *it = line[1][i].wrapping_sub(sum as u8);
+            let sum = (i16::from(line[i - pixel_bytes]) + i16::from(previous[i])) / 2;
+            *it = line[i].wrapping_sub(sum as u8);
         }
-        e.write_all(&buffer)?;
+        e.write_all(buffer)?;
     }
 
     Ok(())
 }
 
-fn filter_paeth<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, e: &mut E) -> ApngResult<()> {
-    fn paeth(left: u8, up_left: u8, up: u8) -> u8 {
-        let w_left = i16::from(left);
-        let w_up = i16::from(up);
-        let w_up_left = i16::from(up_left);
-
-        let base = w_left + w_up - w_up_left;
-        let d_left = (base - w_left).abs();
-        let d_up = (base - w_up).abs();
-        let d_up_left = (base - w_up_left).abs();
-
-        if d_left <= d_up && d_left <= d_up_left {
-            return left;
-        }
-
-        if d_up <= d_up_left {
-            return up;
-        }
-
-        up_left
-    }
-
-    let lines: Vec<&[u8]> = image_data.chunks(row_stride).collect();
-    let mut buffer = vec![0; row_stride];
+fn filter_paeth<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, buffer: &mut Vec<u8>, e: &mut E) -> ApngResult<()> {
+    let first = &image_data[.. row_stride];
+    buffer.resize(row_stride, 0);
 
     e.write_all(&[0x04])?;
-    buffer[..pixel_bytes].clone_from_slice(&lines[0][..pixel_bytes]);
+    buffer[..pixel_bytes].clone_from_slice(&first[..pixel_bytes]);
     for (i, it) in buffer.iter_mut().enumerate().take(row_stride).skip(pixel_bytes) {
-        *it = lines[0][i].wrapping_sub(paeth(lines[0][i - pixel_bytes], 0, 0));
+        *it = first[i].wrapping_sub(paeth(first[i - pixel_bytes], 0, 0));
     }
-    e.write_all(&buffer)?;
+    e.write_all(buffer)?;
 
-    for line in lines.windows(2) {
+    for (previous, line) in row_pairs(image_data, row_stride) {
         e.write_all(&[0x04])?;
         for (i, it) in buffer.iter_mut().enumerate().take(pixel_bytes) {
-            *it = line[1][i].wrapping_sub(paeth(0, 0, line[0][i]));
+            *it = line[i].wrapping_sub(paeth(0, 0, previous[i]));
         }
         for (i, it) in buffer.iter_mut().enumerate().take(row_stride).skip(pixel_bytes) {
-            *it = line[1][i].wrapping_sub(paeth(line[1][i - pixel_bytes], line[0][i - pixel_bytes], line[0][i]));
+            *it = line[i].wrapping_sub(paeth(line[i - pixel_bytes], previous[i - pixel_bytes], previous[i]));
         }
-        e.write_all(&buffer)?;
+        e.write_all(buffer)?;
     }
 
     Ok(())
 }
 
-fn get_compressed_size(filter: Filter, image_data: &[u8], row_stride: usize, pixel_bytes: usize) -> ApngResult<usize> {
-    let mut out = vec![];
-    filter.apply(image_data, row_stride, pixel_bytes, &mut out)?;
-    Ok(out.len())
+/// (previous row, row) for each row after the first row
+fn row_pairs(image_data: &[u8], row_stride: usize) -> impl Iterator<Item = (&[u8], &[u8])> {
+    image_data.chunks(row_stride).zip(image_data.chunks(row_stride).skip(1))
 }
 
-fn infer_best_filter(image_data: &[u8], row_stride: usize, pixel_bytes: usize) -> ApngResult<Filter> {
-    let mut tiny_image_data = vec![];
+/// Choose a filter for each row.
+/// Without `compression`, the filter that gives the minimum sum of absolute differences is chosen,
+/// otherwise the filter that gives the smallest compressed row.
+fn filter_adaptive<E: Write>(image_data: &[u8], row_stride: usize, pixel_bytes: usize, mut trial: Option<(Compression, &mut Trial)>, candidates: &mut Vec<Vec<u8>>, e: &mut E) -> ApngResult<()> {
+    prepare_candidates(candidates, row_stride);
+    let mut previous: Option<&[u8]> = None;
+
+    for line in image_data.chunks(row_stride) {
+        filter_line_adaptive(line, previous, pixel_bytes, trial.as_mut().map(|it| (it.0, &mut *it.1)), candidates, e)?;
+        previous = Some(line);
+    }
+
+    Ok(())
+}
+
+/// Make five buffers of the row size for `filter_line_adaptive`
+pub(super) fn prepare_candidates(candidates: &mut Vec<Vec<u8>>, row_bytes: usize) {
+    candidates.resize_with(5, Vec::new);
+    for it in candidates.iter_mut() {
+        it.resize(row_bytes, 0);
+    }
+}
+
+/// Choose a filter for the row like `filter_adaptive`, and write the filtered row.
+/// `candidates` are five buffers of the row size.
+/// With `trial`, the filter that gives the smallest compressed row is chosen.
+pub(super) fn filter_line_adaptive<E: Write>(line: &[u8], previous: Option<&[u8]>, pixel_bytes: usize, mut trial: Option<(Compression, &mut Trial)>, candidates: &mut [Vec<u8>], e: &mut E) -> ApngResult<()> {
+    let mut best = (Filter::None, std::usize::MAX);
+    for (filter, candidate) in Filter::into_enum_iter().zip(candidates.iter_mut()) {
+        let candidate = &mut candidate[.. line.len()];
+        filter_line(filter, line, previous, pixel_bytes, candidate);
+        let size = match trial {
+            None => candidate.iter().map(|it| i16::from(*it as i8).abs() as usize).sum(),
+            Some((compression, ref mut trial)) => trial.compressed_size(compression, |e| {
+                e.write_all(&[filter as u8])?;
+                e.write_all(candidate)?;
+                Ok(())
+            })?,
+        };
+        if size < best.1 {
+            best = (filter, size);
+        }
+    }
+    e.write_all(&[best.0 as u8])?;
+    e.write_all(&candidates[best.0 as usize][.. line.len()])?;
+    Ok(())
+}
+
+pub(super) fn filter_line(filter: Filter, line: &[u8], previous: Option<&[u8]>, pixel_bytes: usize, out: &mut [u8]) {
+    for (i, it) in out.iter_mut().enumerate() {
+        let left = if pixel_bytes <= i { line[i - pixel_bytes] } else { 0 };
+        let up = previous.map_or(0, |it| it[i]);
+        let up_left = if pixel_bytes <= i { previous.map_or(0, |it| it[i - pixel_bytes]) } else { 0 };
+        let predictor = match filter {
+            Filter::None => 0,
+            Filter::Sub => left,
+            Filter::Up => up,
+            Filter::Average => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            Filter::Paeth => paeth(left, up_left, up),
+        };
+        *it = line[i].wrapping_sub(predictor);
+    }
+}
+
+fn paeth(left: u8, up_left: u8, up: u8) -> u8 {
+    let w_left = i16::from(left);
+    let w_up = i16::from(up);
+    let w_up_left = i16::from(up_left);
+
+    let base = w_left + w_up - w_up_left;
+    let d_left = (base - w_left).abs();
+    let d_up = (base - w_up).abs();
+    let d_up_left = (base - w_up_left).abs();
+
+    if d_left <= d_up && d_left <= d_up_left {
+        return left;
+    }
+
+    if d_up <= d_up_left {
+        return up;
+    }
+
+    up_left
+}
+
+/// Size of the zlib stream of the filtered image data
+fn get_compressed_size(filter: Filter, image_data: &[u8], row_stride: usize, pixel_bytes: usize, compression: Compression, buffer: &mut Vec<u8>, trial: &mut Trial) -> ApngResult<usize> {
+    trial.compressed_size(compression, |e| filter.apply(image_data, row_stride, pixel_bytes, buffer, e))
+}
+
+/// The filter that gives the smallest compressed data for some sample rows
+fn infer_best_filter(image_data: &[u8], row_stride: usize, pixel_bytes: usize, compression: Compression, buffers: &mut FilterBuffers) -> ApngResult<Filter> {
+    let tiny_image_data = &mut buffers.samples;
+    tiny_image_data.clear();
     let len = image_data.len();
     let lines = len / row_stride;
 
@@ -451,23 +1253,121 @@ fn infer_best_filter(image_data: &[u8], row_stride: usize, pixel_bytes: usize) -
     } else {
         tiny_image_data.extend_from_slice(&image_data[0 .. cmp::min(10, lines) * row_stride]);
     }
+    let tiny_image_data = tiny_image_data.as_slice();
 
 
-    let mut results = vec![];
-    for filter in Filter::into_enum_iter() {
-        let size = get_compressed_size(filter, &tiny_image_data, row_stride, pixel_bytes)?;
-        results.push((filter, size));
+    #[cfg(feature = "parallel")]
+    {
+        if buffers.parallel {
+            let results = Filter::into_enum_iter().collect::<Vec<_>>().into_par_iter().map_init(|| (Vec::new(), Trial::default()), |(buffer, trial), filter| {
+                Ok((filter, get_compressed_size(filter, tiny_image_data, row_stride, pixel_bytes, compression, buffer, trial)?))
+            }).collect::<ApngResult<Vec<_>>>()?;
+            return Ok(results.iter().min_by_key(|it| it.1).unwrap().0);
+        }
+    }
+
+    let buffer = &mut buffers.row;
+    let trial = &mut buffers.trial;
+    let mut results = [(Filter::None, 0); 5];
+    for (result, filter) in results.iter_mut().zip(Filter::into_enum_iter()) {
+        *result = (filter, get_compressed_size(filter, tiny_image_data, row_stride, pixel_bytes, compression, buffer, trial)?);
     }
 
-    Ok(results.iter().max_by_key(|it| it.1).unwrap().0)
+    Ok(results.iter().min_by_key(|it| it.1).unwrap().0)
 }
 
 
-fn validate_color(color: Color) -> ApngResult<()> {
+/// Sample of the colors under 8 bits (or 8 bits)
+fn get_sample(line: &[u8], index: usize, bit_depth: u8) -> u8 {
+    let bit_depth = bit_depth as usize;
+    let bit = index * bit_depth;
+    let mask = ((1u16 << bit_depth) - 1) as u8;
+    (line[bit / 8] >> (8 - bit_depth - bit % 8)) & mask
+}
+
+/// Pack one sample per byte into a row of the colors under 8 bits
+pub(super) fn pack_samples(samples: &[u8], color: Color, result: &mut Vec<u8>) -> ApngResult<()> {
+    let bit_depth = color.bit_depth() as usize;
+    let per_byte = 8 / bit_depth;
+    for chunk in samples.chunks(per_byte) {
+        let mut byte = 0u8;
+        for (i, sample) in chunk.iter().enumerate() {
+            if *sample >> bit_depth != 0 {
+                return Err(match color {
+                    Color::Palette(_) => ApngError::InvalidPaletteIndex(*sample),
+                    _ => ApngError::InvalidArgument,
+                });
+            }
+            byte |= sample << (8 - bit_depth * (i + 1));
+        }
+        result.push(byte);
+    }
+    Ok(())
+}
+
+fn write_signature<W: Write>(writer: &mut W) -> ApngResult<()> {
+    writer.write_all(&[0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a])?;
+    Ok(())
+}
+
+fn write_image_header<W: Write>(writer: &mut W, meta: &Meta, options: &EncoderOptions) -> ApngResult<()> {
+    use super::Color::*;
+
+    let mut buffer = vec![];
+    buffer.write_u32::<BigEndian>(meta.width)?;
+    buffer.write_u32::<BigEndian>(meta.height)?;
+    // Alpha - Color - Palette
+    let color_type = match meta.color {
+        Grayscale(_) => 0b000,
+        GrayscaleA(_) => 0b100,
+        Palette(_) => 0b011,
+        RGB(_) => 0b010,
+        RGBA(_) => 0b110,
+    };
+    // ... compression_method, filter_method, interlace_method
+    buffer.write_all(&[meta.color.bit_depth(), color_type, 0, 0, options.interlaced as u8])?;
+    write_chunk(writer, *b"IHDR", &buffer)
+}
+
+fn write_animation_control<W: Write>(writer: &mut W, meta: &Meta) -> ApngResult<()> {
+    let mut buffer = vec![];
+    buffer.write_u32::<BigEndian>(meta.frames)?;
+    buffer.write_u32::<BigEndian>(meta.plays.unwrap_or(0))?;
+    write_chunk(writer, *b"acTL", &buffer)
+}
+
+fn write_chunk<W: Write>(writer: &mut W, chunk_type: [u8;4], chunk_data: &[u8]) -> ApngResult<()> {
+    write_chunk_parts(writer, chunk_type, &[chunk_data])
+}
+
+/// Write a chunk whose data is the concatenation of `parts` without copying them
+fn write_chunk_parts<W: Write>(writer: &mut W, chunk_type: [u8;4], parts: &[&[u8]]) -> ApngResult<()> {
+    let length: usize = parts.iter().map(|it| it.len()).sum();
+    if MAX_CHUNK_LENGTH < length {
+        return Err(ApngError::InvalidArgument);
+    }
+    // Length
+    writer.write_u32::<BigEndian>(length as u32)?;
+    // Type
+    writer.write_all(&chunk_type)?;
+    // Data
+    let mut crc = Crc::new();
+    crc.update(&chunk_type);
+    for part in parts {
+        writer.write_all(part)?;
+        crc.update(part);
+    }
+    // CRC
+    writer.write_u32::<BigEndian>(crc.sum())?;
+    Ok(())
+}
+
+pub(super) fn validate_color(color: Color) -> ApngResult<()> {
     use self::Color::*;
 
     match color {
         Grayscale(b) if [1, 2, 4, 8, 16].contains(&b) => (),
+        Palette(b) if [1, 2, 4, 8].contains(&b) => (),
         GrayscaleA(b) | RGB(b) | RGBA(b) if [8, 16].contains(&b) => (),
         _ => return Err(ApngError::InvalidColor),
     };
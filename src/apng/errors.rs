@@ -9,18 +9,40 @@ pub type ApngResult<T> = Result<T, ApngError>;
 
 #[derive(Fail, Debug)]
 pub enum ApngError {
+    #[fail(display = "Chunk conflicts with the written chunk: {}", 0)]
+    ConflictingChunk(String),
     #[fail(display = "Write a default image at first")]
     DefaultImageNotAtFirst,
     #[fail(display = "Invalid argument")]
     InvalidArgument,
+    #[fail(display = "Invalid chunk: {}", 0)]
+    InvalidChunk(String),
+    #[fail(display = "Chunk is not allowed at this position: {}", 0)]
+    InvalidChunkPosition(String),
     #[fail(display = "Invalid color")]
     InvalidColor,
     #[fail(display = "Invalid default image size or offset")]
     InvalidDefaultImageRectangle,
+    #[fail(display = "Invalid keyword of text chunk")]
+    InvalidKeyword,
+    #[fail(display = "Invalid palette")]
+    InvalidPalette,
+    #[fail(display = "Palette index out of range: {}", 0)]
+    InvalidPaletteIndex(u8),
+    #[fail(display = "Invalid PNG signature")]
+    InvalidSignature,
+    #[fail(display = "Invalid text")]
+    InvalidText,
     #[fail(display = "IO error: {}", 0)]
     Io(IOError),
     #[fail(display = "Default image already exists")]
     MulitiDefaultImage,
+    #[fail(display = "Chunk already exists: {}", 0)]
+    MultiChunk(String),
+    #[fail(display = "Palette already exists")]
+    MultiPalette,
+    #[fail(display = "Palette is required for indexed color")]
+    NoPalette,
     #[fail(display = "Not enough frames: expected={}, actual={}", 0, 1)]
     NotEnoughFrames(usize, usize),
     #[fail(display = "Not enough argument")]
@@ -31,6 +53,8 @@ pub enum ApngError {
     TooManyFrames(usize, usize),
     #[fail(display = "Too small image")]
     TooSmallImage,
+    #[fail(display = "Frame of FrameWriter is not finished")]
+    UnfinishedFrame,
 }
 
 macro_rules! define_error {
@@ -0,0 +1,259 @@
+
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+
+use super::{Color, Frame};
+use super::compressor::ZlibStream;
+use super::encoder::{filter_line, filter_line_adaptive, pack_samples, prepare_candidates};
+use super::encoder::{Compression, Encoder, Filter, FilterStrategy, Rectangle, Trial};
+use super::errors::{ApngResult, ApngError};
+
+
+/// Compressed data is written as IDAT/fdAT chunks when it reaches this size (or `EncoderOptions::max_chunk_size`)
+const IMAGE_DATA_CHUNK_SIZE: usize = 0x8000;
+
+
+/// Writer of the frame (or the default image) that takes rows one by one
+///
+/// Each row is filtered against the previous row and compressed immediately,
+/// and IDAT/fdAT chunks are written when the compressed data is large enough.
+/// The rows are compressed by flate2 at the compression level of `EncoderOptions::compression` (or `frame_writer_with_compression`),
+/// so the encoder must not have a compressor of `Encoder::set_compressor`.
+/// Interlaced image data needs all rows for the first pass, so `EncoderOptions::interlaced` is not supported.
+///
+/// `FilterStrategy::Infer` chooses a filter for each row like `FilterStrategy::Adaptive`, because the following rows are unknown.
+///
+/// The writer uses the buffers of the encoder, and gives them back when it is dropped.
+/// Until `finish` succeeds, the other frames and `Encoder::finish` fail with `ApngError::UnfinishedFrame`,
+/// because the frame control chunk is already written.
+/// When `finish` fails, `FinishError::into_writer` gives the writer back to write the missing rows.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Encoder, Meta};
+///
+/// let meta = Meta { width: 2, height: 1000, color: Color::Grayscale(8), frames: 1, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+/// let mut writer = encoder.frame_writer(None, None).unwrap();
+/// for y in 0 .. 1000 {
+///     writer.write_row(&[(y % 256) as u8, 0xFF]).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// encoder.finish().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FrameWriter<'e, F: io::Write> {
+    candidates: Vec<Vec<u8>>,
+    chunk_type: [u8;4],
+    /// Compressed data that is not written yet
+    compressed: Vec<u8>,
+    compression: Compression,
+    current: Vec<u8>,
+    encoder: &'e mut Encoder<F>,
+    filter: Option<Filter>,
+    previous: Vec<u8>,
+    rect: Rectangle,
+    rows: u32,
+    trial: Trial,
+    zlib: ZlibStream,
+}
+
+/// Error of `FrameWriter::finish`, which has the unfinished writer
+pub struct FinishError<'e, F: io::Write> {
+    error: ApngError,
+    writer: Box<FrameWriter<'e, F>>,
+}
+
+
+impl<F: io::Write> Encoder<F> {
+    /// Start the default image. `FrameWriter::finish` must be called before writing any other chunk.
+    pub fn default_image_writer(&mut self, filter: Option<Filter>) -> ApngResult<FrameWriter<'_, F>> {
+        let compression = self.options().compression;
+        let rect = self.check_streaming(None, compression)?;
+        let chunk_type = self.start_default_image()?;
+        self.set_unfinished_frame(true);
+        Ok(FrameWriter::new(self, rect, filter, compression, chunk_type))
+    }
+
+    /// Start the frame. `FrameWriter::finish` must be called before writing any other chunk.
+    pub fn frame_writer(&mut self, frame: Option<&Frame>, filter: Option<Filter>) -> ApngResult<FrameWriter<'_, F>> {
+        let compression = self.options().compression;
+        self.frame_writer_with_compression(frame, filter, compression)
+    }
+
+    /// `frame_writer` at `compression` instead of `EncoderOptions::compression`
+    pub fn frame_writer_with_compression(&mut self, frame: Option<&Frame>, filter: Option<Filter>, compression: Compression) -> ApngResult<FrameWriter<'_, F>> {
+        let rect = self.check_streaming(frame, compression)?;
+        let chunk_type = self.start_frame(frame)?;
+        self.set_unfinished_frame(true);
+        Ok(FrameWriter::new(self, rect, filter, compression, chunk_type))
+    }
+
+    /// Returns the rectangle of the frame
+    fn check_streaming(&self, frame: Option<&Frame>, compression: Compression) -> ApngResult<Rectangle> {
+        if self.options().interlaced || self.context().has_compressor() {
+            return Err(ApngError::InvalidArgument);
+        }
+        self.context().check_palette()?;
+        compression.validate()?;
+        self.context().compute_rect(frame)
+    }
+}
+
+
+impl<'e, F: io::Write> FrameWriter<'e, F> {
+    fn new(encoder: &'e mut Encoder<F>, rect: Rectangle, filter: Option<Filter>, compression: Compression, chunk_type: [u8;4]) -> Self {
+        let row_bytes = encoder.meta().color.row_bytes(rect.width);
+        let buffers = encoder.buffers_mut();
+        let mut candidates = mem::take(&mut buffers.filters.candidates);
+        prepare_candidates(&mut candidates, row_bytes);
+        let mut compressed = take_cleared(&mut buffers.compressed);
+        let current = take_cleared(&mut buffers.normalized);
+        let previous = take_cleared(&mut buffers.filtered);
+        let trial = mem::take(&mut buffers.filters.trial);
+        let mut zlib = mem::take(&mut buffers.zlib);
+        zlib.start(compression, &mut compressed);
+        FrameWriter {
+            candidates,
+            chunk_type,
+            compressed,
+            compression,
+            current,
+            encoder,
+            filter,
+            previous,
+            rect,
+            rows: 0,
+            trial,
+            zlib,
+        }
+    }
+
+    /// Write the rest of the compressed data.
+    /// The number of the written rows must be the height of the frame.
+    pub fn finish(mut self) -> Result<(), FinishError<'e, F>> {
+        match self.write_rest() {
+            Ok(()) => Ok(()),
+            Err(error) => Err(FinishError { error, writer: Box::new(self) }),
+        }
+    }
+
+    /// Write a row of the frame width.
+    /// Like `row_stride` of `Encoder::write_frame`, the bytes after the row are ignored.
+    pub fn write_row(&mut self, row: &[u8]) -> ApngResult<()> {
+        if self.rect.height <= self.rows {
+            return Err(ApngError::TooLargeImage);
+        }
+
+        let color = self.encoder.meta().color;
+        let unpacked = self.encoder.options().unpacked_samples && color.pixel_bits() < 8;
+        let row_bytes = if unpacked { self.rect.width as usize } else { color.row_bytes(self.rect.width) };
+        if row.len() < row_bytes {
+            return Err(ApngError::InvalidArgument);
+        }
+        self.current.clear();
+        if unpacked {
+            pack_samples(&row[.. row_bytes], color, &mut self.current)?;
+        } else {
+            self.current.extend_from_slice(&row[.. row_bytes]);
+        }
+        self.encoder.context().validate_palette_indices(&self.current, self.current.len(), self.rect)?;
+
+        self.filter_row(color)?;
+        mem::swap(&mut self.current, &mut self.previous);
+        self.rows += 1;
+
+        let chunk_size = self.encoder.image_data_chunk_size(self.chunk_type).min(IMAGE_DATA_CHUNK_SIZE);
+        let ready = self.compressed.len() / chunk_size * chunk_size;
+        if 0 < ready {
+            self.encoder.write_image_data(self.chunk_type, &self.compressed[.. ready])?;
+            self.compressed.drain(.. ready);
+        }
+        Ok(())
+    }
+
+    fn write_rest(&mut self) -> ApngResult<()> {
+        if self.rows < self.rect.height {
+            return Err(ApngError::TooSmallImage);
+        }
+        self.zlib.resume(&mut self.compressed).finish()?;
+        self.encoder.write_image_data(self.chunk_type, &self.compressed)?;
+        self.encoder.set_unfinished_frame(false);
+        Ok(())
+    }
+
+    fn filter_row(&mut self, color: Color) -> ApngResult<()> {
+        let previous = if self.rows == 0 { None } else { Some(self.previous.as_slice()) };
+        let pixel_bytes = color.pixel_bytes();
+        let mut e = self.zlib.resume(&mut self.compressed);
+        match (self.filter, self.encoder.options().filter_strategy) {
+            (Some(filter), _) => {
+                let filtered = &mut self.candidates[0][.. self.current.len()];
+                filter_line(filter, &self.current, previous, pixel_bytes, filtered);
+                e.write_all(&[filter as u8])?;
+                e.write_all(filtered)?;
+                Ok(())
+            },
+            (None, FilterStrategy::Infer) | (None, FilterStrategy::Adaptive) =>
+                filter_line_adaptive(&self.current, previous, pixel_bytes, None, &mut self.candidates, &mut e),
+            (None, FilterStrategy::BruteForce) =>
+                filter_line_adaptive(&self.current, previous, pixel_bytes, Some((self.compression, &mut self.trial)), &mut self.candidates, &mut e),
+        }
+    }
+}
+
+
+impl<'e, F: io::Write> FinishError<'e, F> {
+    pub fn error(&self) -> &ApngError {
+        &self.error
+    }
+
+    /// The writer to write the missing rows and `finish` again
+    pub fn into_writer(self) -> FrameWriter<'e, F> {
+        *self.writer
+    }
+}
+
+/// The writer is not shown, so that `F` does not need `Debug`
+impl<'e, F: io::Write> fmt::Debug for FinishError<'e, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FinishError").field("error", &self.error).finish()
+    }
+}
+
+impl<'e, F: io::Write> fmt::Display for FinishError<'e, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<'e, F: io::Write> From<FinishError<'e, F>> for ApngError {
+    fn from(error: FinishError<'e, F>) -> ApngError {
+        error.error
+    }
+}
+
+
+/// Give the buffers back to the encoder, even if the frame is not finished
+impl<'e, F: io::Write> Drop for FrameWriter<'e, F> {
+    fn drop(&mut self) {
+        let buffers = self.encoder.buffers_mut();
+        buffers.compressed = mem::take(&mut self.compressed);
+        buffers.filters.candidates = mem::take(&mut self.candidates);
+        buffers.filters.trial = mem::take(&mut self.trial);
+        buffers.filtered = mem::take(&mut self.previous);
+        buffers.normalized = mem::take(&mut self.current);
+        buffers.zlib = mem::take(&mut self.zlib);
+    }
+}
+
+
+/// Take the buffer of the encoder as an empty one
+fn take_cleared(buffer: &mut Vec<u8>) -> Vec<u8> {
+    let mut result = mem::take(buffer);
+    result.clear();
+    result
+}
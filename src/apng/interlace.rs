@@ -0,0 +1,79 @@
+
+use super::Color;
+
+
+/// (x, y, dx, dy) of Adam7 passes
+const PASSES: [(u32, u32, u32, u32);7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+
+/// Size of each pass. Passes for too small images can be empty (0 width or 0 height).
+pub(super) fn pass_sizes(width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    PASSES.iter().map(move |&(x, y, dx, dy)| {
+        (count(width, x, dx), count(height, y, dy))
+    })
+}
+
+/// Split packed image data into the passes, and append the non-empty passes (packed image data) to `result`
+pub(super) fn split(image_data: &[u8], color: Color, width: u32, height: u32, result: &mut Vec<u8>) {
+    let row_bytes = color.row_bytes(width);
+    for (&(x0, y0, dx, dy), (pass_width, pass_height)) in PASSES.iter().zip(pass_sizes(width, height)) {
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let pass_row_bytes = color.row_bytes(pass_width);
+        let start = result.len();
+        result.resize(start + pass_row_bytes * pass_height as usize, 0);
+        for (py, line) in result[start ..].chunks_mut(pass_row_bytes).enumerate() {
+            let source = &image_data[(y0 + py as u32 * dy) as usize * row_bytes ..];
+            for px in 0 .. pass_width {
+                copy_pixel(source, (x0 + px * dx) as usize, line, px as usize, color.pixel_bits());
+            }
+        }
+    }
+}
+
+/// Scatter the pixels of a pass into the full image
+pub(super) fn merge(pass: &[u8], index: usize, color: Color, width: u32, image_data: &mut [u8]) {
+    let (x0, y0, dx, dy) = PASSES[index];
+    let pass_width = count(width, x0, dx);
+    if pass_width == 0 {
+        return;
+    }
+    let row_bytes = color.row_bytes(width);
+    for (py, line) in pass.chunks(color.row_bytes(pass_width)).enumerate() {
+        let target = &mut image_data[(y0 + py as u32 * dy) as usize * row_bytes ..];
+        for px in 0 .. pass_width {
+            copy_pixel(line, px as usize, target, (x0 + px * dx) as usize, color.pixel_bits());
+        }
+    }
+}
+
+
+fn count(size: u32, offset: u32, step: u32) -> u32 {
+    if size <= offset { 0 } else { (size - offset + step - 1) / step }
+}
+
+fn copy_pixel(source: &[u8], source_index: usize, target: &mut [u8], target_index: usize, pixel_bits: usize) {
+    if 8 <= pixel_bits {
+        let pixel_bytes = pixel_bits / 8;
+        let source = &source[source_index * pixel_bytes .. (source_index + 1) * pixel_bytes];
+        target[target_index * pixel_bytes .. (target_index + 1) * pixel_bytes].copy_from_slice(source);
+        return;
+    }
+
+    let mask = ((1u16 << pixel_bits) - 1) as u8;
+    let source_bit = source_index * pixel_bits;
+    let sample = (source[source_bit / 8] >> (8 - pixel_bits - source_bit % 8)) & mask;
+    let target_bit = target_index * pixel_bits;
+    let shift = 8 - pixel_bits - target_bit % 8;
+    let it = &mut target[target_bit / 8];
+    *it = (*it & !(mask << shift)) | (sample << shift);
+}
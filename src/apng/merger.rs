@@ -0,0 +1,140 @@
+
+use std::io;
+
+use super::{BlendOperator, Delay, Frame};
+use super::encoder::{Encoder, Filter};
+use super::errors::{ApngResult, ApngError};
+
+
+/// APNG Encoder that merges identical consecutive frames into one frame, summing their delays
+///
+/// The wrapped encoder must be created by `Encoder::create_buffered` or `Encoder::create_seekable`,
+/// because the number of frames is not known until `finish`.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Delay, Encoder, Frame, Meta, MergingEncoder};
+///
+/// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 0, plays: None };
+/// let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+/// let mut buffer = vec![];
+/// let mut encoder = MergingEncoder::new(Encoder::create_buffered(&mut buffer, meta).unwrap()).unwrap();
+/// encoder.write_frame(&[0x00], Some(&frame), None, None).unwrap();
+/// // Merged into the first frame (delay = 2/10)
+/// encoder.write_frame(&[0x00], Some(&frame), None, None).unwrap();
+/// encoder.write_frame(&[0xFF], Some(&frame), None, None).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MergingEncoder<F: io::Write> {
+    encoder: Encoder<F>,
+    pending: Option<Pending>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Pending {
+    /// (numerator, denominator) in seconds
+    delay: (u64, u64),
+    filter: Option<Filter>,
+    frame: Frame,
+    image_data: Vec<u8>,
+    row_stride: Option<usize>,
+}
+
+
+impl<F: io::Write> MergingEncoder<F> {
+    pub fn new(encoder: Encoder<F>) -> ApngResult<Self> {
+        if encoder.is_frame_count_fixed() {
+            return Err(ApngError::InvalidArgument);
+        }
+        Ok(MergingEncoder { encoder, pending: None })
+    }
+
+    pub fn finish(mut self) -> ApngResult<()> {
+        self.flush()?;
+        self.encoder.finish()
+    }
+
+    pub fn write_frame(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        let frame = frame.cloned().unwrap_or_default();
+        let delay = to_fraction(frame.delay.unwrap_or_default());
+
+        if let Some(pending) = self.pending.as_mut() {
+            if pending.is_mergeable(image_data, &frame, row_stride) {
+                pending.delay = add_fractions(pending.delay, delay);
+                return Ok(());
+            }
+        }
+
+        self.flush()?;
+        self.pending = Some(Pending {
+            delay,
+            filter,
+            frame,
+            image_data: image_data.to_vec(),
+            row_stride,
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> ApngResult<()> {
+        if let Some(pending) = self.pending.take() {
+            let frame = Frame { delay: Some(to_delay(pending.delay)), ..pending.frame };
+            self.encoder.write_frame(&pending.image_data, Some(&frame), pending.filter, pending.row_stride)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl Pending {
+    /// Identical frames with `BlendOperator::Over` render a different canvas, so they are not merged.
+    fn is_mergeable(&self, image_data: &[u8], frame: &Frame, row_stride: Option<usize>) -> bool {
+        self.frame.blend_operator.unwrap_or_default() == BlendOperator::Source &&
+            self.row_stride == row_stride &&
+            self.image_data == image_data &&
+            Frame { delay: None, ..self.frame.clone() } == Frame { delay: None, ..frame.clone() }
+    }
+}
+
+
+/// Exact sum, which is rounded only by `to_delay` when the frame is written.
+/// If the exact sum overflows u64, `a` is rounded first.
+fn add_fractions(a: (u64, u64), b: (u64, u64)) -> (u64, u64) {
+    let sum = || -> Option<(u64, u64)> {
+        let denominator = (a.1 / gcd(a.1, b.1)).checked_mul(b.1)?;
+        let numerator = a.0.checked_mul(denominator / a.1)?.checked_add(b.0.checked_mul(denominator / b.1)?)?;
+        Some(reduce((numerator, denominator)))
+    };
+    sum().unwrap_or_else(|| add_fractions(to_fraction(to_delay(a)), b))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn reduce(fraction: (u64, u64)) -> (u64, u64) {
+    let divisor = gcd(fraction.0, fraction.1).max(1);
+    (fraction.0 / divisor, fraction.1 / divisor)
+}
+
+/// Denominator 0 means 1/100 seconds
+fn to_fraction(delay: Delay) -> (u64, u64) {
+    let denominator = if delay.denominator == 0 { 100 } else { delay.denominator };
+    reduce((u64::from(delay.numerator), u64::from(denominator)))
+}
+
+/// Nearest fraction within u16 limits
+fn to_delay(fraction: (u64, u64)) -> Delay {
+    let (numerator, denominator) = (u128::from(fraction.0), u128::from(fraction.1));
+    let max = u128::from(std::u16::MAX);
+    if numerator <= max && denominator <= max {
+        return Delay::new(numerator as u16, denominator as u16);
+    }
+    let denominator_limit = if numerator <= denominator { max } else { (max * denominator / numerator).max(1) };
+    let new_denominator = denominator.min(denominator_limit);
+    let new_numerator = ((numerator * new_denominator + denominator / 2) / denominator).min(max);
+    let (numerator, denominator) = reduce((new_numerator as u64, new_denominator as u64));
+    Delay::new(numerator as u16, denominator as u16)
+}
@@ -0,0 +1,193 @@
+
+use std::io;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::Color;
+use super::encoder::{ChunkPosition, Encoder};
+use super::errors::{ApngResult, ApngError};
+
+
+/// Pixels per unit of pHYs chunk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    pub unit: Unit,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Unit {
+    /// Only the aspect ratio is defined
+    #[default]
+    Unknown = 0,
+    Meter = 1,
+}
+
+/// Background color of bKGD chunk. The variant must match `Meta::color`, and the samples are in its bit depth.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Background {
+    /// For `Color::Grayscale` and `Color::GrayscaleA`
+    Gray(u16),
+    /// For `Color::Palette`
+    PaletteIndex(u8),
+    /// For `Color::RGB` and `Color::RGBA`
+    RGB(u16, u16, u16),
+}
+
+/// Fully transparent color of tRNS chunk for `Color::Grayscale` and `Color::RGB`.
+/// The samples are in the bit depth of `Meta::color`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransparentColor {
+    /// For `Color::Grayscale`
+    Gray(u16),
+    /// For `Color::RGB`
+    RGB(u16, u16, u16),
+}
+
+/// Last modification time (UTC) of tIME chunk
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Timestamp {
+    pub year: u16,
+    /// 1 - 12
+    pub month: u8,
+    /// 1 - 31
+    pub day: u8,
+    /// 0 - 23
+    pub hour: u8,
+    /// 0 - 59
+    pub minute: u8,
+    /// 0 - 60 (for leap seconds)
+    pub second: u8,
+}
+
+
+impl Background {
+    /// Validate the variant and the sample range against `color`.
+    /// Palette indices are validated with the number of palette entries.
+    pub(super) fn validate(self, color: Color, palette_entries: Option<usize>) -> ApngResult<()> {
+        let max = ((1u32 << color.bit_depth()) - 1) as u16;
+        let in_range = |valid: bool| if valid { Ok(()) } else { Err(ApngError::InvalidArgument) };
+        match (self, color) {
+            (Background::Gray(v), Color::Grayscale(_)) | (Background::Gray(v), Color::GrayscaleA(_)) =>
+                in_range(v <= max),
+            (Background::RGB(r, g, b), Color::RGB(_)) | (Background::RGB(r, g, b), Color::RGBA(_)) =>
+                in_range(r.max(g).max(b) <= max),
+            (Background::PaletteIndex(index), Color::Palette(_)) => {
+                let entries = palette_entries.ok_or(ApngError::NoPalette)?;
+                if entries <= index as usize {
+                    return Err(ApngError::InvalidPaletteIndex(index));
+                }
+                Ok(())
+            },
+            _ => Err(ApngError::InvalidColor),
+        }
+    }
+
+    fn to_bytes(self) -> ApngResult<Vec<u8>> {
+        let mut buffer = vec![];
+        match self {
+            Background::Gray(v) => buffer.write_u16::<BigEndian>(v)?,
+            Background::PaletteIndex(index) => buffer.push(index),
+            Background::RGB(r, g, b) => {
+                for it in &[r, g, b] {
+                    buffer.write_u16::<BigEndian>(*it)?;
+                }
+            },
+        }
+        Ok(buffer)
+    }
+}
+
+
+impl TransparentColor {
+    /// Validate the variant and the sample range against `color`
+    pub(super) fn validate(self, color: Color) -> ApngResult<()> {
+        let max = ((1u32 << color.bit_depth()) - 1) as u16;
+        let in_range = |valid: bool| if valid { Ok(()) } else { Err(ApngError::InvalidArgument) };
+        match (self, color) {
+            (TransparentColor::Gray(v), Color::Grayscale(_)) =>
+                in_range(v <= max),
+            (TransparentColor::RGB(r, g, b), Color::RGB(_)) =>
+                in_range(r.max(g).max(b) <= max),
+            _ => Err(ApngError::InvalidColor),
+        }
+    }
+
+    /// Read the data of tRNS chunk. `None` for the other colors.
+    pub(super) fn from_bytes(mut data: &[u8], color: Color) -> ApngResult<Option<Self>> {
+        let result = match color {
+            Color::Grayscale(_) if 2 <= data.len() =>
+                TransparentColor::Gray(data.read_u16::<BigEndian>()?),
+            Color::RGB(_) if 6 <= data.len() =>
+                TransparentColor::RGB(data.read_u16::<BigEndian>()?, data.read_u16::<BigEndian>()?, data.read_u16::<BigEndian>()?),
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    fn to_bytes(self) -> ApngResult<Vec<u8>> {
+        let mut buffer = vec![];
+        match self {
+            TransparentColor::Gray(v) => buffer.write_u16::<BigEndian>(v)?,
+            TransparentColor::RGB(r, g, b) => {
+                for it in &[r, g, b] {
+                    buffer.write_u16::<BigEndian>(*it)?;
+                }
+            },
+        }
+        Ok(buffer)
+    }
+}
+
+
+impl Timestamp {
+    fn validate(self) -> ApngResult<()> {
+        let valid =
+            (1 ..= 12).contains(&self.month) &&
+            (1 ..= 31).contains(&self.day) &&
+            self.hour <= 23 &&
+            self.minute <= 59 &&
+            self.second <= 60;
+        if !valid {
+            return Err(ApngError::InvalidArgument);
+        }
+        Ok(())
+    }
+}
+
+
+impl<F: io::Write> Encoder<F> {
+    /// Write pHYs chunk. It must be written before `write_default_image` and `write_frame`.
+    pub fn write_physical_dimensions(&mut self, dimensions: &PhysicalDimensions) -> ApngResult<()> {
+        let mut buffer = vec![];
+        buffer.write_u32::<BigEndian>(dimensions.x)?;
+        buffer.write_u32::<BigEndian>(dimensions.y)?;
+        buffer.push(dimensions.unit as u8);
+        self.write_unique_chunk(*b"pHYs", &buffer, ChunkPosition::BeforeImageData)
+    }
+
+    /// Write bKGD chunk. It must be written before `write_default_image` and `write_frame`,
+    /// and after `write_palette` for `Color::Palette`.
+    pub fn write_background(&mut self, background: Background) -> ApngResult<()> {
+        background.validate(self.meta().color, self.palette_entries())?;
+        self.write_unique_chunk(*b"bKGD", &background.to_bytes()?, ChunkPosition::BeforeImageData)
+    }
+
+    /// Write tRNS chunk with the fully transparent color for `Color::Grayscale` and `Color::RGB`.
+    /// It must be written before `write_default_image` and `write_frame`.
+    /// For `Color::Palette`, use `write_palette` instead.
+    pub fn write_transparent_color(&mut self, color: TransparentColor) -> ApngResult<()> {
+        color.validate(self.meta().color)?;
+        self.write_unique_chunk(*b"tRNS", &color.to_bytes()?, ChunkPosition::BeforeImageData)
+    }
+
+    /// Write tIME chunk. It can be written at any time before `finish`.
+    pub fn write_timestamp(&mut self, timestamp: &Timestamp) -> ApngResult<()> {
+        timestamp.validate()?;
+        let mut buffer = vec![];
+        buffer.write_u16::<BigEndian>(timestamp.year)?;
+        buffer.extend_from_slice(&[timestamp.month, timestamp.day, timestamp.hour, timestamp.minute, timestamp.second]);
+        self.write_unique_chunk(*b"tIME", &buffer, ChunkPosition::Anywhere)
+    }
+}
@@ -0,0 +1,224 @@
+
+use std::io;
+
+use super::{BlendOperator, Color, Delay, DisposeOperator, Frame};
+use super::encoder::{Encoder, Filter};
+use super::errors::{ApngResult, ApngError};
+
+
+/// APNG Encoder that accepts full canvas frames, and writes only the changed rectangle of each frame
+///
+/// Each frame is written when the next frame arrives (or on `finish`),
+/// because its dispose operator is chosen to minimize the rectangle of the next frame.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Delay, Encoder, Meta, OptimizedEncoder};
+///
+/// let meta = Meta { width: 2, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = OptimizedEncoder::new(Encoder::create(&mut buffer, meta).unwrap()).unwrap();
+/// encoder.write_frame(&[0x00, 0x00], Some(Delay::new(1, 2)), None, None).unwrap();
+/// // Only the second pixel is written
+/// encoder.write_frame(&[0x00, 0xFF], Some(Delay::new(1, 2)), None, None).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct OptimizedEncoder<F: io::Write> {
+    /// Canvas before the pending frame is rendered (`None` for the first frame)
+    base: Option<Vec<u8>>,
+    encoder: Encoder<F>,
+    options: OptimizerOptions,
+    pending: Option<Pending>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct OptimizerOptions {
+    /// Write unchanged pixels as fully transparent pixels with `BlendOperator::Over`, when it is lossless.
+    /// Only for `Color::RGBA` and `Color::GrayscaleA`.
+    pub transparent_pixels: bool,
+}
+
+/// Frame waiting for its dispose operator
+#[derive(Debug)]
+struct Pending {
+    /// Full canvas after the frame is rendered
+    canvas: Vec<u8>,
+    filter: Option<Filter>,
+    frame: Frame,
+    image_data: Vec<u8>,
+    rect: Rect,
+}
+
+/// (left, top, right, bottom)
+type Rect = (usize, usize, usize, usize);
+
+
+impl<F: io::Write> OptimizedEncoder<F> {
+    /// Colors under 8 bit depth are not supported
+    pub fn new(encoder: Encoder<F>) -> ApngResult<Self> {
+        Self::with_options(encoder, OptimizerOptions::default())
+    }
+
+    pub fn with_options(encoder: Encoder<F>, options: OptimizerOptions) -> ApngResult<Self> {
+        if encoder.meta().color.bit_depth() < 8 {
+            return Err(ApngError::InvalidColor);
+        }
+        Ok(OptimizedEncoder { base: None, encoder, options, pending: None })
+    }
+
+    /// Write the last frame, and finish the encoder
+    pub fn finish(mut self) -> ApngResult<()> {
+        if let Some(pending) = self.pending.take() {
+            write_pending(&mut self.encoder, &pending, DisposeOperator::None)?;
+        }
+        self.encoder.finish()
+    }
+
+    /// Write a full canvas frame
+    pub fn write_frame(&mut self, image_data: &[u8], delay: Option<Delay>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        let meta = self.encoder.meta();
+        let (width, height) = (meta.width as usize, meta.height as usize);
+        let pixel_bytes = meta.color.pixel_bytes();
+        let row_bytes = width * pixel_bytes;
+        let (alpha_bytes, has_alpha) = match meta.color {
+            Color::GrayscaleA(b) | Color::RGBA(b) => (if self.options.transparent_pixels { b as usize / 8 } else { 0 }, true),
+            _ => (0, false),
+        };
+        let current = compact(image_data, row_bytes, height, row_stride)?;
+
+        let mut frame = Frame {
+            delay,
+            blend_operator: Some(BlendOperator::Source),
+            ..Default::default()
+        };
+
+        let pending = match self.pending {
+            Some(ref pending) => pending,
+            None => {
+                let rect = (0, 0, width, height);
+                self.pending = Some(Pending { canvas: current.clone(), filter, frame, image_data: current, rect });
+                return Ok(());
+            }
+        };
+
+        // Transparent black is representable only with alpha, and `Previous` is `Background` for the first frame
+        let mut operators = vec![DisposeOperator::None];
+        if has_alpha {
+            operators.push(DisposeOperator::Background);
+        }
+        if self.base.is_some() {
+            operators.push(DisposeOperator::Previous);
+        }
+        let (operator, under, rect) = operators.into_iter().map(|operator| {
+            let under = dispose(pending, self.base.as_ref(), operator, row_bytes, pixel_bytes);
+            let rect = changed_rectangle(&under, &current, width, pixel_bytes).unwrap_or((0, 0, 1, 1));
+            (operator, under, rect)
+        }).min_by_key(|&(_, _, (left, top, right, bottom))| (right - left) * (bottom - top)).unwrap();
+
+        let (left, top, right, bottom) = rect;
+        let mut cropped = crop(&current, row_bytes, pixel_bytes, rect);
+        if 0 < alpha_bytes {
+            let under = crop(&under, row_bytes, pixel_bytes, rect);
+            if substitute_transparent_pixels(&mut cropped, &under, pixel_bytes, alpha_bytes) {
+                frame.blend_operator = Some(BlendOperator::Over);
+            }
+        }
+        frame.x = Some(left as u32);
+        frame.y = Some(top as u32);
+        frame.width = Some((right - left) as u32);
+        frame.height = Some((bottom - top) as u32);
+
+        write_pending(&mut self.encoder, pending, operator)?;
+        self.base = Some(under);
+        self.pending = Some(Pending { canvas: current, filter, frame, image_data: cropped, rect });
+        Ok(())
+    }
+}
+
+
+fn write_pending<F: io::Write>(encoder: &mut Encoder<F>, pending: &Pending, operator: DisposeOperator) -> ApngResult<()> {
+    let frame = Frame { dispose_operator: Some(operator), ..pending.frame.clone() };
+    encoder.write_frame(&pending.image_data, Some(&frame), pending.filter, None)
+}
+
+/// Canvas after the pending frame is disposed with `operator`
+fn dispose(pending: &Pending, base: Option<&Vec<u8>>, operator: DisposeOperator, row_bytes: usize, pixel_bytes: usize) -> Vec<u8> {
+    let mut result = pending.canvas.clone();
+    let (left, top, right, bottom) = pending.rect;
+    let range = left * pixel_bytes .. right * pixel_bytes;
+    for (y, line) in result.chunks_mut(row_bytes).enumerate().skip(top).take(bottom - top) {
+        match (operator, base) {
+            (DisposeOperator::None, _) => break,
+            (DisposeOperator::Previous, Some(base)) =>
+                line[range.clone()].copy_from_slice(&base[y * row_bytes ..][range.clone()]),
+            _ => line[range.clone()].iter_mut().for_each(|it| *it = 0),
+        }
+    }
+    result
+}
+
+fn crop(canvas: &[u8], row_bytes: usize, pixel_bytes: usize, (left, top, right, bottom): Rect) -> Vec<u8> {
+    let mut result = Vec::with_capacity((right - left) * (bottom - top) * pixel_bytes);
+    for line in canvas.chunks(row_bytes).skip(top).take(bottom - top) {
+        result.extend_from_slice(&line[left * pixel_bytes .. right * pixel_bytes]);
+    }
+    result
+}
+
+/// Bounding box (left, top, right, bottom) of the changed pixels
+fn changed_rectangle(previous: &[u8], current: &[u8], width: usize, pixel_bytes: usize) -> Option<Rect> {
+    let mut result: Option<Rect> = None;
+    let lines = previous.chunks(width * pixel_bytes).zip(current.chunks(width * pixel_bytes));
+    for (y, (previous_line, current_line)) in lines.enumerate() {
+        if previous_line == current_line {
+            continue;
+        }
+        let pixels = previous_line.chunks(pixel_bytes).zip(current_line.chunks(pixel_bytes));
+        let left = pixels.clone().position(|(a, b)| a != b).unwrap();
+        let right = width - pixels.rev().position(|(a, b)| a != b).unwrap();
+        result = Some(match result {
+            Some((l, t, r, _)) => (l.min(left), t, r.max(right), y + 1),
+            None => (left, y, right, y + 1),
+        });
+    }
+    result
+}
+
+/// Replace unchanged pixels with fully transparent pixels, only if `BlendOperator::Over` renders exactly the same canvas.
+/// Returns `true` if the pixels are replaced.
+fn substitute_transparent_pixels(pixels: &mut [u8], under: &[u8], pixel_bytes: usize, alpha_bytes: usize) -> bool {
+    let lossless = pixels.chunks(pixel_bytes).zip(under.chunks(pixel_bytes)).all(|(pixel, under)| {
+        let alpha = &pixel[pixel_bytes - alpha_bytes ..];
+        let under_alpha = &under[pixel_bytes - alpha_bytes ..];
+        let opaque = alpha.iter().all(|it| *it == 0xFF);
+        let transparent = alpha.iter().all(|it| *it == 0);
+        pixel == under || opaque || (!transparent && under_alpha.iter().all(|it| *it == 0))
+    });
+    if !lossless {
+        return false;
+    }
+
+    for (pixel, under) in pixels.chunks_mut(pixel_bytes).zip(under.chunks(pixel_bytes)) {
+        if pixel == under {
+            pixel.iter_mut().for_each(|it| *it = 0);
+        }
+    }
+    true
+}
+
+fn compact(image_data: &[u8], row_bytes: usize, height: usize, row_stride: Option<usize>) -> ApngResult<Vec<u8>> {
+    let row_stride = row_stride.unwrap_or(row_bytes);
+    if row_stride < row_bytes {
+        return Err(ApngError::InvalidArgument);
+    }
+    if image_data.len() < row_stride * height.saturating_sub(1) + row_bytes {
+        return Err(ApngError::TooSmallImage);
+    }
+    let mut result = Vec::with_capacity(row_bytes * height);
+    for line in image_data.chunks(row_stride).take(height) {
+        result.extend_from_slice(&line[.. row_bytes]);
+    }
+    Ok(result)
+}
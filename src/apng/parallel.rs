@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use super::Frame;
+use super::encoder::{Encoder, Filter};
+use super::errors::ApngResult;
+
+
+/// APNG Encoder that filters and compresses frames on the rayon thread pool
+///
+/// Each frame is compressed by a worker thread, and the compressed frames are written on the caller's thread in the given order,
+/// so the output is identical to `Encoder`'s one.
+/// At most `queue_size` frames are compressed at once, and `write_frame` waits for the oldest frame when the queue is full.
+/// An error of the compression is returned by the `write_frame` (or `finish`) that writes the frame.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Encoder, Meta, ParallelEncoder};
+///
+/// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 3, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = ParallelEncoder::new(Encoder::create(&mut buffer, meta).unwrap());
+/// for value in &[0x00, 0x80, 0xFF] {
+///     encoder.write_frame(&[*value], None, None, None).unwrap();
+/// }
+/// encoder.finish().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ParallelEncoder<F: io::Write> {
+    encoder: Encoder<F>,
+    queue: VecDeque<Pending>,
+    queue_size: usize,
+}
+
+/// Frame compressed by a worker thread
+#[derive(Debug)]
+struct Pending {
+    compressed: Receiver<thread::Result<ApngResult<Vec<u8>>>>,
+    frame: Option<Frame>,
+}
+
+
+impl<F: io::Write> ParallelEncoder<F> {
+    /// Queue size is twice the number of threads in the pool
+    pub fn new(encoder: Encoder<F>) -> Self {
+        Self::with_queue_size(encoder, rayon::current_num_threads() * 2)
+    }
+
+    pub fn with_queue_size(encoder: Encoder<F>, queue_size: usize) -> Self {
+        let queue_size = queue_size.max(1);
+        ParallelEncoder { encoder, queue: VecDeque::with_capacity(queue_size), queue_size }
+    }
+
+    pub fn finish(mut self) -> ApngResult<()> {
+        self.write_queued(1)?;
+        self.encoder.finish()
+    }
+
+    /// Write the default image immediately (not in parallel)
+    pub fn write_default_image(&mut self, image_data: &[u8], filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        self.write_queued(1)?;
+        self.encoder.write_default_image(image_data, filter, row_stride)
+    }
+
+    pub fn write_frame(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        self.encoder.check_frame_count(self.queue.len() + 1)?;
+        // The queued frames are written before this one
+        if self.queue.is_empty() {
+            self.encoder.check_frame_rectangle(frame)?;
+        } else {
+            self.encoder.context().compute_rect(frame)?;
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let context = self.encoder.context().clone();
+        let image_data = image_data.to_vec();
+        let worker_frame = frame.cloned();
+        rayon::spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                context.compress_frame(&image_data, worker_frame.as_ref(), filter, row_stride)
+            }));
+            // The receiver is gone if the encoder is dropped
+            let _ = sender.send(result);
+        });
+        self.queue.push_back(Pending { compressed: receiver, frame: frame.cloned() });
+
+        self.write_queued(self.queue_size)
+    }
+
+    /// Write the compressed frames at the front of the queue.
+    /// It waits for the workers while the queue has `limit` frames or more, and writes the already compressed frames after that.
+    fn write_queued(&mut self, limit: usize) -> ApngResult<()> {
+        while let Some(pending) = self.queue.front() {
+            let result = if limit <= self.queue.len() {
+                pending.compressed.recv().expect("Worker thread is gone")
+            } else {
+                match pending.compressed.try_recv() {
+                    Ok(result) => result,
+                    Err(TryRecvError::Empty) => return Ok(()),
+                    Err(TryRecvError::Disconnected) => panic!("Worker thread is gone"),
+                }
+            };
+            let pending = self.queue.pop_front().unwrap();
+            let compressed = result.unwrap_or_else(|payload| panic::resume_unwind(payload))?;
+            self.encoder.write_compressed_frame(&compressed, pending.frame.as_ref())?;
+        }
+        Ok(())
+    }
+}
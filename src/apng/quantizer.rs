@@ -0,0 +1,326 @@
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io;
+
+use super::{Color, Frame, Meta};
+use super::encoder::{Encoder, Filter, Rectangle};
+use super::errors::{ApngResult, ApngError};
+
+
+/// APNG Encoder that quantizes RGB/RGBA frames into one shared palette
+///
+/// All frames are buffered until `finish`, then written as `Color::Palette(8)` through `Encoder::write_frame`.
+///
+/// # Example
+///
+/// ```
+/// use apng_encoder::{Color, Meta, QuantizedEncoder, QuantizerOptions};
+///
+/// let meta = Meta { width: 2, height: 1, color: Color::RGB(8), frames: 2, plays: None };
+/// let mut buffer = vec![];
+/// let mut encoder = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+/// encoder.write_frame(&[0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00], None, None, None).unwrap();
+/// encoder.write_frame(&[0x00, 0xFF, 0x00, 0xFF, 0x00, 0x00], None, None, None).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct QuantizedEncoder<F: io::Write> {
+    default_image: Option<Source>,
+    frames: Vec<Source>,
+    meta: Meta,
+    options: QuantizerOptions,
+    writer: F,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct QuantizerOptions {
+    /// Maximum number of palette entries (1 - 256)
+    pub colors: usize,
+    /// Use Floyd-Steinberg dithering
+    pub dithering: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Source {
+    filter: Option<Filter>,
+    frame: Option<Frame>,
+    height: usize,
+    image_data: Vec<u8>,
+    row_stride: usize,
+    width: usize,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Palette {
+    entries: Vec<[u8;4]>,
+    cache: HashMap<[u8;4], u8>,
+}
+
+
+impl<F: io::Write> QuantizedEncoder<F> {
+    /// `meta.color` must be `Color::RGB(8)` or `Color::RGBA(8)`
+    pub fn create(writer: F, meta: Meta, options: QuantizerOptions) -> ApngResult<Self> {
+        match meta.color {
+            Color::RGB(8) | Color::RGBA(8) => (),
+            _ => return Err(ApngError::InvalidColor),
+        }
+        if options.colors == 0 || 256 < options.colors {
+            return Err(ApngError::InvalidArgument);
+        }
+        Ok(QuantizedEncoder {
+            default_image: None,
+            frames: vec![],
+            meta,
+            options,
+            writer,
+        })
+    }
+
+    /// Quantize all frames, and write them
+    pub fn finish(self) -> ApngResult<()> {
+        let pixel_bytes = self.meta.color.pixel_bytes();
+        let sources = self.default_image.iter().chain(self.frames.iter());
+
+        let mut histogram = HashMap::<[u8;4], usize>::new();
+        for source in sources.clone() {
+            for pixel in source.pixels(pixel_bytes) {
+                *histogram.entry(pixel).or_insert(0) += 1;
+            }
+        }
+        let mut palette = Palette::new(median_cut(histogram, self.options.colors));
+
+        let meta = Meta { color: Color::Palette(8), ..self.meta };
+        let mut encoder = Encoder::create(self.writer, meta)?;
+        let (rgb, alpha) = palette.to_chunks();
+        encoder.write_palette(&rgb, alpha.as_deref())?;
+
+        if let Some(source) = self.default_image.as_ref() {
+            let indices = source.quantize(&mut palette, pixel_bytes, self.options.dithering);
+            encoder.write_default_image(&indices, source.filter, None)?;
+        }
+        for source in &self.frames {
+            let indices = source.quantize(&mut palette, pixel_bytes, self.options.dithering);
+            encoder.write_frame(&indices, source.frame.as_ref(), source.filter, None)?;
+        }
+
+        encoder.finish()
+    }
+
+    pub fn write_default_image(&mut self, image_data: &[u8], filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        if self.default_image.is_some() {
+            return Err(ApngError::MulitiDefaultImage);
+        }
+        if !self.frames.is_empty() {
+            return Err(ApngError::DefaultImageNotAtFirst);
+        }
+        self.default_image = Some(self.make_source(image_data, None, filter, row_stride)?);
+        Ok(())
+    }
+
+    pub fn write_frame(&mut self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<()> {
+        if (self.meta.frames as usize) <= self.frames.len() {
+            return Err(ApngError::TooManyFrames(self.meta.frames as usize, self.frames.len() + 1));
+        }
+        // The first frame is the default image too, unless `write_default_image` is called
+        if self.default_image.is_none() && self.frames.is_empty() && Rectangle::of_frame(&self.meta, frame).modified {
+            return Err(ApngError::InvalidDefaultImageRectangle);
+        }
+        let source = self.make_source(image_data, frame, filter, row_stride)?;
+        self.frames.push(source);
+        Ok(())
+    }
+
+    /// Validate the frame like `Encoder::write_frame`, so that `finish` does not fail after all frames are buffered
+    fn make_source(&self, image_data: &[u8], frame: Option<&Frame>, filter: Option<Filter>, row_stride: Option<usize>) -> ApngResult<Source> {
+        let rect = Rectangle::of_frame(&self.meta, frame);
+        rect.validate(&self.meta)?;
+        let width = rect.width as usize;
+        let height = rect.height as usize;
+        let row_bytes = width * self.meta.color.pixel_bytes();
+        let row_stride = row_stride.unwrap_or(row_bytes);
+        if row_stride < row_bytes {
+            return Err(ApngError::InvalidArgument);
+        }
+        // The last row does not need the padding
+        if image_data.len() < row_stride * (height - 1) + row_bytes {
+            return Err(ApngError::TooSmallImage);
+        }
+        Ok(Source {
+            filter,
+            frame: frame.cloned(),
+            height,
+            image_data: image_data.to_vec(),
+            row_stride,
+            width,
+        })
+    }
+}
+
+
+impl Default for QuantizerOptions {
+    fn default() -> Self {
+        QuantizerOptions { colors: 256, dithering: false }
+    }
+}
+
+
+impl Source {
+    fn pixels(&self, pixel_bytes: usize) -> impl Iterator<Item = [u8;4]> + '_ {
+        let width = self.width;
+        self.image_data.chunks(self.row_stride).take(self.height).flat_map(move |line| {
+            line[.. width * pixel_bytes].chunks(pixel_bytes).map(to_rgba)
+        })
+    }
+
+    fn quantize(&self, palette: &mut Palette, pixel_bytes: usize, dithering: bool) -> Vec<u8> {
+        if !dithering {
+            return self.pixels(pixel_bytes).map(|it| palette.nearest(it)).collect();
+        }
+
+        let width = self.width;
+        let mut errors = vec![[0.0f32;4]; width * 2];
+        let mut result = Vec::with_capacity(width * self.height);
+
+        for line in self.image_data.chunks(self.row_stride).take(self.height) {
+            let (current, next) = errors.split_at_mut(width);
+            for (x, pixel) in line[.. width * pixel_bytes].chunks(pixel_bytes).enumerate() {
+                let source = to_rgba(pixel);
+                let mut wanted = [0u8;4];
+                for c in 0 .. 4 {
+                    wanted[c] = to_sample(f32::from(source[c]) + current[x][c]);
+                }
+                let index = palette.nearest(wanted);
+                result.push(index);
+
+                let actual = palette.entries[index as usize];
+                for c in 0 .. 4 {
+                    let error = f32::from(source[c]) + current[x][c] - f32::from(actual[c]);
+                    if x + 1 < width {
+                        current[x + 1][c] += error * 7.0 / 16.0;
+                        next[x + 1][c] += error / 16.0;
+                    }
+                    if 0 < x {
+                        next[x - 1][c] += error * 3.0 / 16.0;
+                    }
+                    next[x][c] += error * 5.0 / 16.0;
+                }
+            }
+            current.copy_from_slice(next);
+            for it in next.iter_mut() {
+                *it = [0.0;4];
+            }
+        }
+
+        result
+    }
+}
+
+
+impl Palette {
+    fn new(entries: Vec<[u8;4]>) -> Self {
+        Palette { entries, cache: HashMap::new() }
+    }
+
+    fn nearest(&mut self, color: [u8;4]) -> u8 {
+        let entries = &self.entries;
+        *self.cache.entry(color).or_insert_with(|| {
+            let distance = |entry: &[u8;4]| -> u32 {
+                entry.iter().zip(color.iter()).map(|(a, b)| {
+                    let d = i32::from(*a) - i32::from(*b);
+                    (d * d) as u32
+                }).sum()
+            };
+            let (index, _) = entries.iter().enumerate().min_by_key(|(_, it)| distance(it)).unwrap();
+            index as u8
+        })
+    }
+
+    /// PLTE and tRNS data
+    fn to_chunks(&self) -> (Vec<u8>, Option<Vec<u8>>) {
+        let rgb = self.entries.iter().flat_map(|it| it[.. 3].to_vec()).collect();
+        let mut alpha: Vec<u8> = self.entries.iter().map(|it| it[3]).collect();
+        while alpha.last() == Some(&0xFF) {
+            alpha.pop();
+        }
+        (rgb, if alpha.is_empty() { None } else { Some(alpha) })
+    }
+}
+
+
+fn to_rgba(pixel: &[u8]) -> [u8;4] {
+    [pixel[0], pixel[1], pixel[2], pixel.get(3).cloned().unwrap_or(0xFF)]
+}
+
+/// Round and saturate the dithered value
+fn to_sample(value: f32) -> u8 {
+    if value <= 0.0 {
+        0
+    } else if 255.0 <= value {
+        0xFF
+    } else {
+        value.round() as u8
+    }
+}
+
+/// Median cut: Split the box that has the widest channel range at its weighted median, until we get `colors` boxes.
+fn median_cut(histogram: HashMap<[u8;4], usize>, colors: usize) -> Vec<[u8;4]> {
+    let mut pixels: Vec<([u8;4], usize)> = histogram.into_iter().collect();
+    if pixels.is_empty() {
+        return vec![[0, 0, 0, 0xFF]];
+    }
+    if pixels.len() <= colors {
+        pixels.sort();
+        return pixels.into_iter().map(|it| it.0).collect();
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < colors {
+        let widest = boxes.iter().enumerate()
+            .filter(|(_, it)| 1 < it.len())
+            .map(|(index, it)| (index, widest_channel(it)))
+            .max_by_key(|(_, (_, range))| *range);
+        let (index, channel) = match widest {
+            Some((index, (channel, _))) => (index, channel),
+            None => break,
+        };
+
+        let mut target = boxes.swap_remove(index);
+        target.sort_by_key(|it| it.0[channel]);
+        let total: usize = target.iter().map(|it| it.1).sum();
+        let mut sum = 0;
+        let mut median = 1;
+        for (i, it) in target.iter().enumerate() {
+            sum += it.1;
+            if total <= sum * 2 {
+                median = i + 1;
+                break;
+            }
+        }
+        let median = cmp::max(1, cmp::min(median, target.len() - 1));
+        let rest = target.split_off(median);
+        boxes.push(target);
+        boxes.push(rest);
+    }
+
+    let mut result: Vec<[u8;4]> = boxes.iter().map(|pixels| {
+        let total = pixels.iter().map(|it| it.1 as u64).sum::<u64>();
+        let mut color = [0u8;4];
+        for (c, it) in color.iter_mut().enumerate() {
+            let sum: u64 = pixels.iter().map(|(pixel, count)| u64::from(pixel[c]) * *count as u64).sum();
+            *it = ((sum + total / 2) / total) as u8;
+        }
+        color
+    }).collect();
+    result.sort();
+    result.dedup();
+    result
+}
+
+fn widest_channel(pixels: &[([u8;4], usize)]) -> (usize, u8) {
+    (0 .. 4).map(|c| {
+        let min = pixels.iter().map(|it| it.0[c]).min().unwrap();
+        let max = pixels.iter().map(|it| it.0[c]).max().unwrap();
+        (c, max - min)
+    }).max_by_key(|it| it.1).unwrap()
+}
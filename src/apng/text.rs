@@ -0,0 +1,130 @@
+
+use std::io::{self, Write};
+
+use flate2::write::ZlibEncoder;
+
+use super::encoder::Encoder;
+use super::errors::{ApngResult, ApngError};
+
+
+/// Chunk type and encoding of text
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum TextFormat {
+    /// tEXt chunk (Latin-1)
+    #[default]
+    Latin1,
+    /// zTXt chunk (Latin-1, compressed)
+    CompressedLatin1,
+    /// iTXt chunk (UTF-8)
+    Utf8 {
+        compressed: bool,
+        /// Language tag (e.g. "en", "ja-JP"). Empty for unknown language.
+        language: String,
+        /// Keyword translated into the language
+        translated_keyword: String,
+    },
+}
+
+
+impl<F: io::Write> Encoder<F> {
+    /// Write a tEXt, zTXt or iTXt chunk.
+    /// Text chunks can be written at any time before `finish`.
+    ///
+    /// `keyword` must be 1-79 Latin-1 characters (e.g. "Title", "Author", "Copyright").
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use apng_encoder::{Color, Encoder, Meta, TextFormat};
+    ///
+    /// let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    /// let mut buffer = vec![];
+    /// let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    /// encoder.write_text("Title", "Black dot", TextFormat::Latin1).unwrap();
+    /// encoder.write_frame(&[0x00], None, None, None).unwrap();
+    /// encoder.finish().unwrap();
+    /// ```
+    pub fn write_text(&mut self, keyword: &str, text: &str, format: TextFormat) -> ApngResult<()> {
+        let mut buffer = to_keyword(keyword)?;
+        buffer.push(0);
+
+        let chunk_type = match format {
+            TextFormat::Latin1 => {
+                buffer.extend_from_slice(&to_latin1(text)?);
+                *b"tEXt"
+            },
+            TextFormat::CompressedLatin1 => {
+                // Compression method
+                buffer.push(0);
+                self.compress_metadata(&to_latin1(text)?, &mut buffer)?;
+                *b"zTXt"
+            },
+            TextFormat::Utf8 { compressed, language, translated_keyword } => {
+                validate_language(&language)?;
+                if translated_keyword.contains('\0') {
+                    return Err(ApngError::InvalidText);
+                }
+                // Compression flag, Compression method
+                buffer.write_all(&[compressed as u8, 0])?;
+                buffer.extend_from_slice(language.as_bytes());
+                buffer.push(0);
+                buffer.extend_from_slice(translated_keyword.as_bytes());
+                buffer.push(0);
+                if compressed {
+                    self.compress_metadata(text.as_bytes(), &mut buffer)?;
+                } else {
+                    buffer.extend_from_slice(text.as_bytes());
+                }
+                *b"iTXt"
+            },
+        };
+
+        self.write_chunk(chunk_type, &buffer)
+    }
+
+    /// zlib stream for zTXt, iTXt and iCCP
+    pub(super) fn compress_metadata(&self, data: &[u8], buffer: &mut Vec<u8>) -> ApngResult<()> {
+        let mut e = ZlibEncoder::new(buffer, self.options().compression.to_level());
+        e.write_all(data)?;
+        e.finish()?;
+        Ok(())
+    }
+}
+
+
+/// Keywords are 1-79 printable Latin-1 characters, without leading, trailing and consecutive spaces
+pub(super) fn to_keyword(keyword: &str) -> ApngResult<Vec<u8>> {
+    let result = to_latin1(keyword).map_err(|_| ApngError::InvalidKeyword)?;
+    let printable = |it: &u8| (0x20 ..= 0x7E).contains(it) || 0xA1 <= *it;
+    if result.is_empty() || 79 < result.len() || !result.iter().all(printable) {
+        return Err(ApngError::InvalidKeyword);
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(ApngError::InvalidKeyword);
+    }
+    Ok(result)
+}
+
+fn to_latin1(text: &str) -> ApngResult<Vec<u8>> {
+    text.chars().map(|it| {
+        match it as u32 {
+            0 => Err(ApngError::InvalidText),
+            code if code <= 0xFF => Ok(code as u8),
+            _ => Err(ApngError::InvalidText),
+        }
+    }).collect()
+}
+
+/// Language tags are hyphen separated words of ASCII letters and digits
+fn validate_language(language: &str) -> ApngResult<()> {
+    if language.is_empty() {
+        return Ok(());
+    }
+    let valid = language.split('-').all(|word| {
+        !word.is_empty() && word.chars().all(|it| it.is_ascii_alphanumeric())
+    });
+    if !valid {
+        return Err(ApngError::InvalidText);
+    }
+    Ok(())
+}
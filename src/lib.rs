@@ -8,5 +8,19 @@ extern crate test;
 mod apng;
 
 pub use apng::*;
+#[cfg(feature = "async")]
+pub use apng::asynchronous::*;
+pub use apng::color_space::*;
+pub use apng::compositor::*;
+pub use apng::compressor::*;
+pub use apng::decoder::*;
 pub use apng::encoder::*;
 pub use apng::errors::*;
+pub use apng::frame_writer::*;
+pub use apng::merger::*;
+pub use apng::metadata::*;
+pub use apng::optimizer::*;
+#[cfg(feature = "parallel")]
+pub use apng::parallel::*;
+pub use apng::quantizer::*;
+pub use apng::text::*;
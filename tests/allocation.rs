@@ -0,0 +1,61 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use apng_encoder::{Encoder, EncoderOptions, FilterStrategy};
+use apng_encoder::{Color, Meta};
+
+
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+
+/// Number of the allocations for the second half of the frames
+fn count_allocations(filter_strategy: FilterStrategy, rows: bool) -> usize {
+    let meta = Meta { width: 32, height: 32, color: Color::RGB(8), frames: 10, plays: None };
+    let options = EncoderOptions { filter_strategy, ..Default::default() };
+    let image_data: Vec<u8> = (0 .. 32 * 32 * 3).map(|it| (it * 7 % 251) as u8).collect();
+    let mut encoder = Encoder::create_with_options(io::sink(), meta, options).unwrap();
+    let mut before = 0;
+    for i in 0 .. 10 {
+        if i == 5 {
+            before = ALLOCATIONS.load(Ordering::SeqCst);
+        }
+        if rows {
+            let mut writer = encoder.frame_writer(None, None).unwrap();
+            for row in image_data.chunks(32 * 3) {
+                writer.write_row(row).unwrap();
+            }
+            writer.finish().unwrap();
+        } else {
+            encoder.write_frame(&image_data, None, None, None).unwrap();
+        }
+    }
+    ALLOCATIONS.load(Ordering::SeqCst) - before
+}
+
+
+// In one test, because the allocations of the other tests are counted too
+#[test]
+fn test_steady_state_allocations() {
+    for filter_strategy in &[FilterStrategy::Infer, FilterStrategy::Adaptive, FilterStrategy::BruteForce] {
+        assert_eq!(count_allocations(*filter_strategy, false), 0);
+        assert_eq!(count_allocations(*filter_strategy, true), 0);
+    }
+}
@@ -0,0 +1,127 @@
+#![cfg(feature = "async")]
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+
+use apng_encoder::{AsyncEncoder, Encoder};
+use apng_encoder::{Color, Delay, Frame, Meta};
+
+mod common;
+use common::load_sources;
+
+
+
+/// Writer that takes a few bytes at once, and is not ready every other time
+#[derive(Default)]
+struct SlowWriter {
+    data: Vec<u8>,
+    ready: bool,
+}
+
+impl AsyncWrite for SlowWriter {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let size = buf.len().min(3);
+        self.data.extend_from_slice(&buf[.. size]);
+        Poll::Ready(Ok(size))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn generate(sources: &[Vec<u8>], meta: Meta) -> Vec<u8> {
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = Encoder::create(vec![], meta).unwrap();
+    for source in sources {
+        encoder.write_frame(source, Some(&frame), None, None).unwrap();
+    }
+    encoder.into_inner().unwrap()
+}
+
+#[tokio::test]
+async fn test_async_output_is_identical() {
+    let (meta, sources) = load_sources();
+    let expected = generate(&sources, meta.clone());
+
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = AsyncEncoder::new(Encoder::create(vec![], meta).unwrap(), vec![]);
+    let mut written = 0;
+    for source in &sources {
+        encoder.write_frame(source, Some(&frame), None, None).await.unwrap();
+        // Each frame is sent immediately
+        assert!(written < encoder.get_ref().len());
+        written = encoder.get_ref().len();
+    }
+    assert_eq!(encoder.into_inner().await.unwrap(), expected);
+}
+
+#[tokio::test]
+async fn test_async_buffered() {
+    let (meta, sources) = load_sources();
+    let expected = generate(&sources, meta.clone());
+
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut buffer = vec![];
+    let mut encoder = AsyncEncoder::new(Encoder::create_buffered(vec![], Meta { frames: 1, ..meta }).unwrap(), &mut buffer);
+    for source in &sources {
+        encoder.write_frame(source, Some(&frame), None, None).await.unwrap();
+    }
+    encoder.finish().await.unwrap();
+    assert_eq!(buffer, expected);
+}
+
+#[tokio::test]
+async fn test_async_custom_chunk() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut encoder = AsyncEncoder::new(Encoder::create(vec![], meta).unwrap(), vec![]);
+    encoder.write_frame(&[0x00], None, None, None).await.unwrap();
+    encoder.encoder_mut().write_custom_chunk(*b"hiTb", &[1]).unwrap();
+    encoder.write_frame(&[0xFF], None, None, None).await.unwrap();
+    let png = encoder.into_inner().await.unwrap();
+    assert!(png.windows(4).any(|it| it == b"hiTb"));
+}
+
+#[tokio::test]#[should_panic(expected="TooManyFrames(1, 2)")]
+async fn test_async_many_frames_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut encoder = AsyncEncoder::new(Encoder::create(vec![], meta).unwrap(), vec![]);
+    encoder.write_frame(&[0x00], None, None, None).await.unwrap();
+    encoder.write_frame(&[0xFF], None, None, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_cancelled_flush() {
+    let meta = Meta { width: 4, height: 4, color: Color::Grayscale(8), frames: 1, plays: None };
+    let sources = vec![(0 .. 16).collect::<Vec<u8>>()];
+    let expected = generate(&sources, meta.clone());
+
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = AsyncEncoder::new(Encoder::create(vec![], meta).unwrap(), SlowWriter::default());
+    encoder.encoder_mut().write_frame(&sources[0], Some(&frame), None, None).unwrap();
+    let mut cancelled = 0;
+    loop {
+        // `yield_now` is ready at the second poll, and cancels `flush` in the middle
+        tokio::select! {
+            biased;
+            result = encoder.flush() => break result.unwrap(),
+            _ = tokio::task::yield_now() => cancelled += 1,
+        }
+        // The sent bytes must not be sent again
+        assert!(cancelled < 1000);
+    }
+    assert!(0 < cancelled);
+    assert_eq!(encoder.into_inner().await.unwrap().data, expected);
+}
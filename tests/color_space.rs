@@ -0,0 +1,152 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use apng_encoder::{decode, Chromaticities, CodingIndependentCodePoints, ContentLightLevel, Encoder, MasteringDisplay, RenderingIntent};
+use apng_encoder::{Color, Meta};
+
+mod common;
+use common::read_chunks;
+
+
+
+fn palette_meta() -> Meta {
+    Meta { width: 1, height: 1, color: Color::Palette(8), frames: 1, plays: None }
+}
+
+#[test]
+fn test_color_space_chunks() {
+    let chromaticities = Chromaticities {
+        white: (31270, 32900),
+        red: (64000, 33000),
+        green: (30000, 60000),
+        blue: (15000, 6000),
+    };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    encoder.write_srgb(RenderingIntent::RelativeColorimetric).unwrap();
+    encoder.write_gamma(45455).unwrap();
+    encoder.write_chromaticities(&chromaticities).unwrap();
+    encoder.write_palette(&[0xFF, 0x00, 0x00], None).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    encoder.finish().unwrap();
+    decode(&mut buffer.as_slice()).unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "sRGB", "gAMA", "cHRM", "PLTE", "fcTL", "IDAT", "IEND"]);
+    assert_eq!(chunks[2].1, &[1]);
+    assert_eq!(chunks[3].1, &[0x00, 0x00, 0xB1, 0x8F]);
+    assert_eq!(&chunks[4].1[.. 8], &[0x00, 0x00, 0x7A, 0x26, 0x00, 0x00, 0x80, 0x84]);
+}
+
+#[test]
+fn test_icc_profile() {
+    let profile: Vec<u8> = (0 .. 1000).map(|it| (it % 7) as u8).collect();
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_icc_profile("Display P3", &profile).unwrap();
+    encoder.write_frame(&[0xFF, 0x00, 0x00], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let (_, data) = read_chunks(&buffer).into_iter().find(|it| it.0 == "iCCP").unwrap();
+    assert_eq!(&data[.. 12], b"Display P3\0\0");
+    let mut inflated = vec![];
+    ZlibDecoder::new(&data[12 ..]).read_to_end(&mut inflated).unwrap();
+    assert_eq!(inflated, profile);
+}
+
+#[test]#[should_panic(expected="ConflictingChunk(\"iCCP\")")]
+fn test_srgb_and_icc_profile_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    encoder.write_srgb(RenderingIntent::Perceptual).unwrap();
+    encoder.write_icc_profile("Display P3", &[0; 16]).unwrap();
+}
+
+#[test]#[should_panic(expected="ConflictingChunk(\"sRGB\")")]
+fn test_icc_profile_and_srgb_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    encoder.write_icc_profile("Display P3", &[0; 16]).unwrap();
+    encoder.write_srgb(RenderingIntent::Perceptual).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunkPosition(\"gAMA\")")]
+fn test_after_palette_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    encoder.write_palette(&[0xFF, 0x00, 0x00], None).unwrap();
+    encoder.write_gamma(45455).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunkPosition(\"sRGB\")")]
+fn test_after_image_data_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    encoder.write_srgb(RenderingIntent::Perceptual).unwrap();
+}
+
+#[test]#[should_panic(expected="MultiChunk(\"gAMA\")")]
+fn test_multi_gamma_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    encoder.write_gamma(45455).unwrap();
+    encoder.write_gamma(100000).unwrap();
+}
+
+#[test]
+fn test_hdr_chunks() {
+    let display = MasteringDisplay {
+        red: (35400, 14600),
+        green: (8500, 39850),
+        blue: (6550, 2300),
+        white: (15635, 16450),
+        max_luminance: 10_000_000,
+        min_luminance: 1,
+    };
+    let level = ContentLightLevel { max_content: 10_000_000, max_frame_average: 4_000_000 };
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(16), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_code_points(&CodingIndependentCodePoints::BT2100_PQ).unwrap();
+    encoder.write_mastering_display(&display).unwrap();
+    encoder.write_content_light_level(&level).unwrap();
+    encoder.write_frame(&[0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00], None, None, None).unwrap();
+    encoder.finish().unwrap();
+    decode(&mut buffer.as_slice()).unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "cICP", "mDCv", "cLLi", "fcTL", "IDAT", "IEND"]);
+    assert_eq!(chunks[2].1, &[9, 16, 0, 1]);
+    assert_eq!(chunks[3].1.len(), 24);
+    assert_eq!(&chunks[3].1[16 ..], &[0x00, 0x98, 0x96, 0x80, 0x00, 0x00, 0x00, 0x01]);
+    assert_eq!(chunks[4].1, &[0x00, 0x98, 0x96, 0x80, 0x00, 0x3D, 0x09, 0x00]);
+}
+
+#[test]#[should_panic(expected="InvalidColor")]
+fn test_hdr_code_points_color_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    encoder.write_code_points(&CodingIndependentCodePoints::BT2100_HLG).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_code_points_matrix_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, palette_meta()).unwrap();
+    let code_points = CodingIndependentCodePoints { matrix_coefficients: 9, ..CodingIndependentCodePoints::BT2100_PQ };
+    encoder.write_code_points(&code_points).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidColor")]
+fn test_content_light_level_color_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(4), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_content_light_level(&ContentLightLevel { max_content: 1000, max_frame_average: 100 }).unwrap();
+}
@@ -0,0 +1,53 @@
+// Each test crate uses a part of the fixtures
+#![allow(dead_code)]
+
+use std::fs::File;
+
+use image::ImageDecoder;
+use image::png::PNGDecoder;
+
+use apng_encoder::{Color, Meta};
+
+
+
+/// (chunk type, chunk data) after the signature
+pub fn read_chunks(png: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut result = vec![];
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let chunk_type = String::from_utf8_lossy(&rest[4 .. 8]).into_owned();
+        result.push((chunk_type, rest[8 .. 8 + length].to_vec()));
+        rest = &rest[12 + length ..];
+    }
+    result
+}
+
+
+pub fn load_source(filepath: &str) -> (Meta, Vec<u8>) {
+    let source_file = File::open(filepath).unwrap();
+    let decoder = PNGDecoder::new(source_file).unwrap();
+    let (width, height) = decoder.dimensions();
+    let meta = Meta {
+        width: width as u32,
+        height: height as u32,
+        color: Color::RGB(8),
+        frames: 0,
+        plays: None, // Infinite loop
+    };
+    (meta, decoder.read_image().unwrap())
+}
+
+
+pub fn load_sources() -> (Meta, Vec<Vec<u8>>) {
+    let (mut meta, image_data) = load_source("test-files/1.png");
+    let mut result = vec![image_data];
+    meta.frames = 4;
+
+    for i in 2 ..= 4 {
+        let (_, image_data) = load_source(&format!("test-files/{}.png", i));
+        result.push(image_data);
+    }
+
+    (meta, result)
+}
@@ -0,0 +1,134 @@
+use apng_encoder::{decode, Background, Compositor, Encoder};
+use apng_encoder::{BlendOperator, Color, DisposeOperator, Frame, Meta};
+
+
+
+fn sub_frame(x: u32, dispose_operator: DisposeOperator, blend_operator: BlendOperator) -> Frame {
+    Frame {
+        x: Some(x),
+        y: Some(0),
+        width: Some(1),
+        height: Some(1),
+        dispose_operator: Some(dispose_operator),
+        blend_operator: Some(blend_operator),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_dispose_none() {
+    let meta = Meta { width: 2, height: 1, color: Color::RGB(8), frames: 2, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.composite(&Frame::default(), &[1, 1, 1,   2, 2, 2]).unwrap();
+    let canvas = compositor.composite(&sub_frame(1, DisposeOperator::None, BlendOperator::Source), &[3, 3, 3]).unwrap();
+    assert_eq!(canvas, &[1, 1, 1, 0xFF,   3, 3, 3, 0xFF]);
+}
+
+#[test]
+fn test_dispose_background() {
+    let meta = Meta { width: 2, height: 1, color: Color::RGB(8), frames: 3, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.composite(&Frame::default(), &[1, 1, 1,   2, 2, 2]).unwrap();
+    compositor.composite(&sub_frame(1, DisposeOperator::Background, BlendOperator::Source), &[3, 3, 3]).unwrap();
+    let canvas = compositor.composite(&sub_frame(0, DisposeOperator::None, BlendOperator::Source), &[4, 4, 4]).unwrap();
+    assert_eq!(canvas, &[4, 4, 4, 0xFF,   0, 0, 0, 0]);
+}
+
+#[test]
+fn test_dispose_previous() {
+    let meta = Meta { width: 2, height: 1, color: Color::RGB(8), frames: 3, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.composite(&Frame::default(), &[1, 1, 1,   2, 2, 2]).unwrap();
+    let canvas = compositor.composite(&sub_frame(1, DisposeOperator::Previous, BlendOperator::Source), &[3, 3, 3]).unwrap();
+    assert_eq!(canvas, &[1, 1, 1, 0xFF,   3, 3, 3, 0xFF]);
+    let canvas = compositor.composite(&sub_frame(0, DisposeOperator::None, BlendOperator::Source), &[4, 4, 4]).unwrap();
+    assert_eq!(canvas, &[4, 4, 4, 0xFF,   2, 2, 2, 0xFF]);
+}
+
+#[test]
+fn test_dispose_previous_at_first() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    let frame = Frame { dispose_operator: Some(DisposeOperator::Previous), ..Default::default() };
+    compositor.composite(&frame, &[9]).unwrap();
+    let canvas = compositor.composite(&sub_frame(0, DisposeOperator::None, BlendOperator::Over), &[9]).unwrap();
+    assert_eq!(canvas, &[9, 9, 9, 0xFF]);
+}
+
+#[test]
+fn test_blend_over_grayscale_alpha_16() {
+    let meta = Meta { width: 1, height: 1, color: Color::GrayscaleA(16), frames: 2, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.composite(&Frame::default(), &[0xFF, 0xFF, 0xFF, 0xFF]).unwrap();
+    let frame = Frame { blend_operator: Some(BlendOperator::Over), ..Default::default() };
+    // Black with alpha = 0x8000
+    let canvas = compositor.composite(&frame, &[0x00, 0x00, 0x80, 0x00]).unwrap();
+    assert_eq!(canvas, &[0x7F, 0xFF, 0x7F, 0xFF, 0x7F, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_blend_over_transparent_canvas() {
+    let meta = Meta { width: 1, height: 1, color: Color::RGBA(8), frames: 1, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    let frame = Frame { blend_operator: Some(BlendOperator::Over), ..Default::default() };
+    let canvas = compositor.composite(&frame, &[10, 20, 30, 40]).unwrap();
+    assert_eq!(canvas, &[10, 20, 30, 40]);
+}
+
+#[test]
+fn test_composite_decoded_palette() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 1, color: Color::Palette(8), frames: 2, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0xFF, 0, 0,   0, 0xFF, 0], Some(&[0xFF, 0])).unwrap();
+    encoder.write_frame(&[0, 0], None, None, None).unwrap();
+    let frame = Frame { blend_operator: Some(BlendOperator::Over), ..Default::default() };
+    encoder.write_frame(&[1, 0], Some(&frame), None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let canvases = Compositor::composite_decoded(&decode(&mut buffer.as_slice()).unwrap()).unwrap();
+    assert_eq!(canvases.len(), 2);
+    assert_eq!(canvases[0], &[0xFF, 0, 0, 0xFF,   0xFF, 0, 0, 0xFF]);
+    assert_eq!(canvases[1], canvases[0]);
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_composite_rectangle_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.composite(&sub_frame(1, DisposeOperator::None, BlendOperator::Source), &[0, 0, 0]).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_composite_rectangle_overflow_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.composite(&sub_frame(0xFFFFFFFF, DisposeOperator::None, BlendOperator::Source), &[0, 0, 0]).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_canvas_size_overflow_validation() {
+    let meta = Meta { width: 0xFFFFFFFF, height: 0xFFFFFFFF, color: Color::RGB(8), frames: 1, plays: None };
+    Compositor::new(&meta).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_canvas_size_validation() {
+    // 32 GiB of the canvas
+    let meta = Meta { width: 0x10000, height: 0x10000, color: Color::RGB(8), frames: 1, plays: None };
+    Compositor::new(&meta).unwrap();
+}
+
+#[test]
+fn test_background() {
+    let meta = Meta { width: 2, height: 1, color: Color::GrayscaleA(8), frames: 2, plays: None };
+    let mut compositor = Compositor::new(&meta).unwrap();
+    compositor.set_background(Background::Gray(0xFF)).unwrap();
+    let canvas = compositor.composite(&Frame::default(), &[0x00, 0xFF,   0x00, 0x80]).unwrap();
+    assert_eq!(canvas, &[0x00, 0x00, 0x00, 0xFF,   0x7F, 0x7F, 0x7F, 0xFF]);
+
+    // Disposed region shows the background
+    compositor.composite(&sub_frame(1, DisposeOperator::Background, BlendOperator::Source), &[0x00, 0xFF]).unwrap();
+    let canvas = compositor.composite(&sub_frame(0, DisposeOperator::None, BlendOperator::Over), &[0x00, 0x00]).unwrap();
+    assert_eq!(canvas, &[0x00, 0x00, 0x00, 0xFF,   0xFF, 0xFF, 0xFF, 0xFF]);
+}
@@ -0,0 +1,72 @@
+use apng_encoder::{decode, Encoder};
+use apng_encoder::{Color, Meta};
+
+mod common;
+use common::read_chunks;
+
+
+
+fn meta() -> Meta {
+    Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None }
+}
+
+#[test]
+fn test_custom_chunks() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta()).unwrap();
+    encoder.write_custom_chunk(*b"vrSn", b"1.2.0").unwrap();
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    encoder.write_custom_chunk(*b"hiTb", &[1]).unwrap();
+    encoder.write_frame(&[0xFF], None, None, None).unwrap();
+    encoder.write_custom_chunk(*b"hiTb", &[2]).unwrap();
+    encoder.finish().unwrap();
+    decode(&mut buffer.as_slice()).unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "vrSn", "fcTL", "IDAT", "hiTb", "fcTL", "fdAT", "hiTb", "IEND"]);
+    assert_eq!(chunks[2].1, b"1.2.0");
+    assert_eq!(chunks[5].1, &[1]);
+    assert_eq!(chunks[8].1, &[2]);
+}
+
+#[test]
+fn test_buffered_custom_chunks() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_buffered(&mut buffer, meta()).unwrap();
+    encoder.write_custom_chunk(*b"vrSN", b"1.2.0").unwrap();
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "vrSN", "fcTL", "IDAT", "IEND"]);
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"VrSn\")")]
+fn test_critical_chunk_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta()).unwrap();
+    encoder.write_custom_chunk(*b"VrSn", &[]).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"vRSn\")")]
+fn test_public_chunk_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta()).unwrap();
+    encoder.write_custom_chunk(*b"vRSn", &[]).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"vrsn\")")]
+fn test_reserved_bit_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta()).unwrap();
+    encoder.write_custom_chunk(*b"vrsn", &[]).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"vr1n\")")]
+fn test_non_letter_chunk_validation() {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta()).unwrap();
+    encoder.write_custom_chunk(*b"vr1n", &[]).unwrap();
+}
@@ -0,0 +1,173 @@
+use std::fs::File;
+
+use apng_encoder::{decode, Encoder, Filter};
+use apng_encoder::{BlendOperator, Color, Delay, DisposeOperator, Frame, Meta};
+
+mod common;
+use common::load_source;
+
+
+
+fn round_trip(filter: Option<Filter>) {
+    let (mut meta, first) = load_source("test-files/1.png");
+    let (_, second) = load_source("test-files/2.png");
+    meta.frames = 2;
+    meta.plays = Some(3);
+
+    let frame = Frame {
+        delay: Some(Delay::new(1, 10)),
+        dispose_operator: Some(DisposeOperator::Background),
+        blend_operator: Some(BlendOperator::Over),
+        ..Default::default()
+    };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta.clone()).unwrap();
+    encoder.write_frame(&first, Some(&frame), filter, None).unwrap();
+    encoder.write_frame(&second, Some(&frame), filter, None).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    let expected_frame = Frame {
+        width: Some(meta.width),
+        height: Some(meta.height),
+        x: Some(0),
+        y: Some(0),
+        ..frame
+    };
+    assert_eq!(decoded.meta, meta);
+    assert_eq!(decoded.default_image, None);
+    assert_eq!(decoded.frames.len(), 2);
+    assert_eq!(decoded.frames[0].frame, expected_frame);
+    assert_eq!(decoded.frames[0].image_data, first);
+    assert_eq!(decoded.frames[1].frame, expected_frame);
+    assert_eq!(decoded.frames[1].image_data, second);
+}
+
+#[test]
+fn test_decode_without_filter() {
+    round_trip(Some(Filter::None));
+}
+
+#[test]
+fn test_decode_sub_filter() {
+    round_trip(Some(Filter::Sub));
+}
+
+#[test]
+fn test_decode_up_filter() {
+    round_trip(Some(Filter::Up));
+}
+
+#[test]
+fn test_decode_average_filter() {
+    round_trip(Some(Filter::Average));
+}
+
+#[test]
+fn test_decode_paeth_filter() {
+    round_trip(Some(Filter::Paeth));
+}
+
+#[test]
+fn test_decode_static_png() {
+    let (mut meta, expected) = load_source("test-files/3.png");
+    meta.frames = 0;
+    let decoded = decode(&mut File::open("test-files/3.png").unwrap()).unwrap();
+    assert_eq!(decoded.meta, meta);
+    assert_eq!(decoded.default_image, Some(expected));
+    assert!(decoded.frames.is_empty());
+}
+
+#[test]
+fn test_decode_default_image_and_offset() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 3, height: 2, color: Color::GrayscaleA(16), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let default_image: Vec<u8> = (0 .. 24).collect();
+    encoder.write_default_image(&default_image, None, None).unwrap();
+    let frame = Frame { x: Some(1), y: Some(1), width: Some(2), height: Some(1), ..Default::default() };
+    encoder.write_frame(&[1, 2, 3, 4, 5, 6, 7, 8], Some(&frame), None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.default_image, Some(default_image));
+    assert_eq!(decoded.frames[0].frame.x, Some(1));
+    assert_eq!(decoded.frames[0].frame.y, Some(1));
+    assert_eq!(decoded.frames[0].image_data, &[1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn test_decode_palette() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 1, color: Color::Palette(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0xFF, 0, 0,   0, 0xFF, 0], Some(&[0x80])).unwrap();
+    encoder.write_frame(&[1, 0], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.palette, Some(vec![0xFF, 0, 0,   0, 0xFF, 0]));
+    assert_eq!(decoded.transparency, Some(vec![0x80]));
+    assert_eq!(decoded.frames[0].image_data, &[1, 0]);
+}
+
+#[test]#[should_panic(expected="InvalidSignature")]
+fn test_decode_signature_validation() {
+    decode(&mut [0u8; 16].as_ref()).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"IHDR\")")]
+fn test_decode_crc_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    encoder.finish().unwrap();
+    buffer[20] ^= 0xFF;
+    decode(&mut buffer.as_slice()).unwrap();
+}
+
+/// Overwrite the chunk data at `offset`, and fix its CRC
+fn patch_chunk(png: &mut [u8], chunk_type: &[u8], offset: usize, data: &[u8]) {
+    let position = png.windows(4).position(|it| it == chunk_type).unwrap();
+    let length = u32::from_be_bytes([png[position - 4], png[position - 3], png[position - 2], png[position - 1]]) as usize;
+    png[position + 4 + offset .. position + 4 + offset + data.len()].copy_from_slice(data);
+    let mut crc = flate2::Crc::new();
+    crc.update(&png[position .. position + 4 + length]);
+    png[position + 4 + length .. position + 8 + length].copy_from_slice(&crc.sum().to_be_bytes());
+}
+
+fn encode_black(width: u32, height: u32, color: Color) -> Vec<u8> {
+    let mut buffer = vec![];
+    let meta = Meta { width, height, color, frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&vec![0; color.row_bytes(width) * height as usize], None, None, None).unwrap();
+    encoder.finish().unwrap();
+    buffer
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"fcTL\")")]
+fn test_decode_frame_rectangle_validation() {
+    let mut buffer = encode_black(2, 2, Color::Grayscale(8));
+    // x = 1
+    patch_chunk(&mut buffer, b"fcTL", 12, &[0, 0, 0, 1]);
+    decode(&mut buffer.as_slice()).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunk(\"fcTL\")")]
+fn test_decode_frame_rectangle_overflow_validation() {
+    let mut buffer = encode_black(2, 2, Color::Grayscale(8));
+    // y = 2^32 - 1
+    patch_chunk(&mut buffer, b"fcTL", 16, &[0xFF; 4]);
+    decode(&mut buffer.as_slice()).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_decode_image_size_overflow_validation() {
+    let mut buffer = encode_black(2, 2, Color::RGBA(16));
+    for chunk_type in &[b"IHDR", b"fcTL"] {
+        let offset = if *chunk_type == b"IHDR" { 0 } else { 4 };
+        patch_chunk(&mut buffer, *chunk_type, offset, &[0xFF; 8]);
+    }
+    decode(&mut buffer.as_slice()).unwrap();
+}
@@ -1,16 +1,20 @@
 use std::fs::{create_dir, File};
-use std::io::Write;
+use std::io::{Cursor, Write};
 
 use image::ImageDecoder;
 use image::png::PNGDecoder;
 use rand::prelude::*;
 
-use apng_encoder::{Encoder, Filter};
+use apng_encoder::{decode, ApngError, Compression, Compressor, Encoder, EncoderOptions, Filter, FilterStrategy};
+use apng_encoder::{FlateCompressor, StoredCompressor};
 use apng_encoder::{Color, Delay, Frame, Meta};
 
 #[cfg(feature = "benchmark")]
 use test::Bencher;
 
+mod common;
+use common::{load_source, load_sources, read_chunks};
+
 
 
 const FOUR: [u8;12] = [
@@ -21,40 +25,12 @@ const FOUR: [u8;12] = [
 ];
 
 
-fn load_source(filepath: &str) -> (Meta, Vec<u8>) {
-    let source_file = File::open(filepath).unwrap();
-    let decoder = PNGDecoder::new(source_file).unwrap();
-    let (width, height) = decoder.dimensions();
-    let meta = Meta {
-        width: width as u32,
-        height: height as u32,
-        color: Color::RGB(8),
-        frames: 0,
-        plays: None, // Infinite loop
-    };
-    (meta, decoder.read_image().unwrap())
-}
-
-
-fn load_sources() -> (Meta, Vec<Vec<u8>>) {
-    let (mut meta, image_data) = load_source("test-files/1.png");
-    let mut result = vec![image_data];
-    meta.frames = 4;
-
-    for i in 2 ..= 4 {
-        let (_, image_data) = load_source(&format!("test-files/{}.png", i));
-        result.push(image_data);
-    }
-
-    (meta, result)
-}
-
 fn generate_png<F: Write>(file: &mut F, sources: &[Vec<u8>], meta: Meta, filter: Option<Filter>) {
     // Delay = 2 seconds
     let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
     let mut encoder = Encoder::create(file, meta).unwrap();
     for source in sources {
-        encoder.write_frame(&source, Some(&frame), filter, None).unwrap();
+        encoder.write_frame(source, Some(&frame), filter, None).unwrap();
     }
     encoder.finish().unwrap();
 }
@@ -102,8 +78,7 @@ fn test_too_large_validation() {
     let mut buffer = vec![];
     let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
     let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
-    let mut image_data = vec![];
-    image_data.resize(1000, 0);
+    let image_data = vec![0; 1000];
     encoder.write_frame(&image_data, None, None, None).unwrap();
 }
 
@@ -115,6 +90,26 @@ fn test_too_small_validation() {
     encoder.write_frame(&[0x00], None, None, None).unwrap();
 }
 
+#[test]#[should_panic(expected="TooSmallImage")]
+fn test_zero_width_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 2, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&FOUR, None, None, None).unwrap();
+    let frame = Frame { width: Some(0), ..Default::default() };
+    encoder.write_frame(&[0x00], Some(&frame), None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="TooSmallImage")]
+fn test_zero_height_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 2, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&FOUR, None, None, None).unwrap();
+    let frame = Frame { height: Some(0), ..Default::default() };
+    encoder.write_frame(&[], Some(&frame), None, None).unwrap();
+}
+
 #[test]#[should_panic(expected="TooLargeImage")]
 fn test_too_large_validation_with_offset_x() {
     let mut buffer = vec![];
@@ -125,6 +120,16 @@ fn test_too_large_validation_with_offset_x() {
     encoder.write_frame(&FOUR, Some(&frame), None, None).unwrap();
 }
 
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_too_large_validation_with_overflowing_offset() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 2, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let frame = Frame { x: Some(0xFFFFFFFF), width: Some(2), ..Default::default() };
+    encoder.write_frame(&FOUR, None, None, None).unwrap();
+    encoder.write_frame(&FOUR, Some(&frame), None, None).unwrap();
+}
+
 #[test]#[should_panic(expected="TooLargeImage")]
 fn test_too_large_validation_with_offset_y() {
     let mut buffer = vec![];
@@ -196,6 +201,532 @@ fn test_default_image_count_validation() {
     encoder.write_default_image(&FOUR, None, None).unwrap();
 }
 
+#[test]#[should_panic(expected="NoPalette")]
+fn test_palette_existence_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Palette(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0, 1, 2, 3], None, None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidPalette")]
+fn test_palette_size_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Palette(1), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0; 9], None).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidPalette")]
+fn test_palette_transparency_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Palette(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0; 12], Some(&[])).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidPaletteIndex(4)")]
+fn test_palette_index_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Palette(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0; 12], None).unwrap();
+    encoder.write_frame(&[0, 1, 2, 4], None, None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="MultiPalette")]
+fn test_palette_count_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Palette(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0; 12], None).unwrap();
+    encoder.write_palette(&[0; 12], None).unwrap();
+}
+
+#[test]
+fn test_generate_palette() {
+    let mut file = create_file("palette.png");
+    let meta = Meta { width: 2, height: 2, color: Color::Palette(8), frames: 2, plays: None };
+    let frame = Frame { delay: Some(Delay::new(1, 2)), ..Default::default() };
+    let mut encoder = Encoder::create(&mut file, meta).unwrap();
+    encoder.write_palette(
+        &[
+            0xFF, 0x00, 0x00,
+            0x00, 0xFF, 0x00,
+            0x00, 0x00, 0xFF,
+            0x00, 0x00, 0x00,
+        ],
+        Some(&[0xFF, 0xFF, 0xFF, 0x00])).unwrap();
+    encoder.write_frame(&[0, 1, 2, 3], Some(&frame), None, None).unwrap();
+    encoder.write_frame(&[3, 2, 1, 0], Some(&frame), None, None).unwrap();
+    encoder.finish().unwrap();
+}
+
+#[test]
+fn test_seekable_frame_count() {
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let mut cursor = Cursor::new(vec![]);
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = Encoder::create_seekable(&mut cursor, Meta { frames: 0, ..meta }).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, Some(&frame), None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+    assert_eq!(cursor.into_inner(), expected);
+}
+
+#[test]
+fn test_buffered_frame_count() {
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let mut buffer = vec![];
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = Encoder::create_buffered(&mut buffer, Meta { frames: 1, ..meta }).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, Some(&frame), None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+    assert_eq!(buffer, expected);
+}
+
+#[test]#[should_panic(expected="NotEnoughFrames(1, 0)")]
+fn test_buffered_frame_count_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let encoder = Encoder::create_buffered(&mut buffer, meta).unwrap();
+    encoder.finish().unwrap();
+}
+
+#[test]
+fn test_encoder_equality() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut a = Encoder::create_seekable(Cursor::new(vec![]), meta.clone()).unwrap();
+    let b = Encoder::create_seekable(Cursor::new(vec![]), meta.clone()).unwrap();
+    assert_eq!(a, b);
+    assert_ne!(a, Encoder::create_buffered(Cursor::new(vec![]), meta).unwrap());
+    a.write_frame(&[0], None, None, None).unwrap();
+    assert_ne!(a, b);
+}
+
+#[test]
+fn test_owned_writer() {
+    struct Session {
+        encoder: Encoder<Vec<u8>>,
+    }
+
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let mut session = Session { encoder: Encoder::create(vec![], meta).unwrap() };
+    let session = std::thread::spawn(move || {
+        let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+        for source in &sources {
+            session.encoder.write_frame(source, Some(&frame), None, None).unwrap();
+        }
+        session
+    }).join().unwrap();
+    assert_eq!(&session.encoder.get_ref()[.. 8], &expected[.. 8]);
+    assert_eq!(session.encoder.into_inner().unwrap(), expected);
+}
+
+#[test]
+fn test_into_inner_error() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut encoder = Encoder::create(vec![], meta.clone()).unwrap();
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    let error = encoder.into_inner().unwrap_err();
+    assert!(matches!(error.error(), ApngError::NotEnoughFrames(2, 1)));
+    let written = error.into_encoder().get_ref().clone();
+
+    // The missing frame is written to the encoder given back
+    let mut encoder = Encoder::create(vec![], meta).unwrap();
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    let mut encoder = encoder.into_inner().unwrap_err().into_encoder();
+    encoder.write_frame(&[0xFF], None, None, None).unwrap();
+    let png = encoder.into_inner().unwrap();
+    assert_eq!(decode(&mut png.as_slice()).unwrap().frames.len(), 2);
+
+    // The writer is given back without IEND
+    assert_eq!(&png[.. written.len()], written.as_slice());
+}
+
+#[test]
+fn test_encode_to_vec() {
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let frames = sources.iter().map(|it| (it.as_slice(), Some(&frame)));
+    assert_eq!(apng_encoder::encode_to_vec(Meta { frames: 0, ..meta }, frames).unwrap(), expected);
+}
+
+fn generate_palette_png(plays: u32) -> Vec<u8> {
+    let meta = Meta { width: 2, height: 1, color: Color::Palette(8), frames: 2, plays: Some(plays) };
+    let mut encoder = Encoder::create(vec![], meta).unwrap();
+    encoder.write_palette(&[0xFF, 0x00, 0x00,   0x00, 0xFF, 0x00], None).unwrap();
+    encoder.write_frame(&[0, 1], None, None, None).unwrap();
+    encoder.write_frame(&[1, 0], None, Some(Filter::Sub), None).unwrap();
+    encoder.into_inner().unwrap()
+}
+
+#[test]
+fn test_reset() {
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = Encoder::create(vec![], meta.clone()).unwrap();
+    for plays in 1 ..= 2 {
+        for source in &sources {
+            encoder.write_frame(source, Some(&frame), None, None).unwrap();
+        }
+        let palette_meta = Meta { width: 2, height: 1, color: Color::Palette(8), frames: 2, plays: Some(plays) };
+        assert_eq!(encoder.reset(vec![], palette_meta).unwrap(), expected);
+
+        // The palette and the sequence numbers of the previous image are forgotten
+        encoder.write_palette(&[0xFF, 0x00, 0x00,   0x00, 0xFF, 0x00], None).unwrap();
+        encoder.write_frame(&[0, 1], None, None, None).unwrap();
+        encoder.write_frame(&[1, 0], None, Some(Filter::Sub), None).unwrap();
+        assert_eq!(encoder.reset(vec![], meta.clone()).unwrap(), generate_palette_png(plays));
+    }
+}
+
+#[test]
+fn test_reset_unfixed_frame_count() {
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut encoder = Encoder::create_buffered(vec![], Meta { frames: 1, ..meta.clone() }).unwrap();
+    for _ in 0 .. 2 {
+        for source in &sources {
+            encoder.write_frame(source, Some(&frame), None, None).unwrap();
+        }
+        assert_eq!(encoder.reset(vec![], meta.clone()).unwrap(), expected);
+        assert!(!encoder.is_frame_count_fixed());
+    }
+
+    let mut encoder = Encoder::create_seekable(Cursor::new(vec![]), Meta { frames: 0, ..meta.clone() }).unwrap();
+    for _ in 0 .. 2 {
+        for source in &sources {
+            encoder.write_frame(source, Some(&frame), None, None).unwrap();
+        }
+        assert_eq!(encoder.reset(Cursor::new(vec![]), meta.clone()).unwrap().into_inner(), expected);
+        assert!(!encoder.is_frame_count_fixed());
+    }
+}
+
+/// Writer that fails if it is broken
+#[derive(Debug, Default)]
+struct BrokenWriter {
+    broken: bool,
+    data: Vec<u8>,
+}
+
+impl Write for BrokenWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.broken {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_reset_with_broken_writer() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut expected = vec![];
+    let mut encoder = Encoder::create(&mut expected, meta.clone()).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    // The current image is kept, when the new writer fails
+    let mut encoder = Encoder::create(BrokenWriter::default(), meta.clone()).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    assert!(encoder.reset(BrokenWriter { broken: true, data: vec![] }, meta).is_err());
+    assert_eq!(encoder.into_inner().unwrap().data, expected);
+}
+
+#[test]#[should_panic(expected="NotEnoughFrames(2, 1)")]
+fn test_reset_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut encoder = Encoder::create(vec![], meta.clone()).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    encoder.reset(vec![], meta).unwrap();
+}
+
+fn generate_png_with_compression(options: EncoderOptions, frame_compression: Option<Compression>) -> Vec<u8> {
+    let (meta, sources) = load_sources();
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    for source in &sources {
+        match frame_compression {
+            Some(compression) => encoder.write_frame_with_compression(source, None, Some(Filter::Sub), None, compression).unwrap(),
+            None => encoder.write_frame(source, None, Some(Filter::Sub), None).unwrap(),
+        }
+    }
+    encoder.finish().unwrap();
+    buffer
+}
+
+#[test]
+fn test_compression_level() {
+    let stored = generate_png_with_compression(EncoderOptions { compression: Compression::Level(0), ..Default::default() }, None);
+    let fast = generate_png_with_compression(EncoderOptions { compression: Compression::Fast, ..Default::default() }, None);
+    let best = generate_png_with_compression(EncoderOptions::default(), None);
+    assert!(best.len() <= fast.len());
+    assert!(fast.len() < stored.len());
+
+    let overridden = generate_png_with_compression(EncoderOptions::default(), Some(Compression::Level(0)));
+    assert_eq!(overridden, stored);
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_compression_level_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let options = EncoderOptions { compression: Compression::Level(10), ..Default::default() };
+    let _ = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_frame_compression_level_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame_with_compression(&FOUR, None, None, None, Compression::Level(10)).unwrap();
+}
+
+fn generate_png_with_compressor(compressor: Box<dyn Compressor>) -> Vec<u8> {
+    let (meta, image_data) = load_source("test-files/1.png");
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, Meta { frames: 1, ..meta }).unwrap();
+    encoder.set_compressor(compressor);
+    encoder.write_frame(&image_data, None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[0].image_data, image_data);
+    let decoder = PNGDecoder::new(buffer.as_slice()).unwrap();
+    assert_eq!(decoder.read_image().unwrap(), image_data);
+    buffer
+}
+
+#[test]
+fn test_stored_compressor() {
+    let stored = generate_png_with_compressor(Box::new(StoredCompressor));
+    let flate = generate_png_with_compressor(Box::new(FlateCompressor));
+    assert!(flate.len() < stored.len());
+}
+
+#[test]
+fn test_streaming_compression() {
+    // The default encoder deflates rows as they are filtered, and makes the same stream as `FlateCompressor`
+    let (meta, image_data) = load_source("test-files/1.png");
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, Meta { frames: 1, ..meta }).unwrap();
+    encoder.write_frame(&image_data, None, None, None).unwrap();
+    encoder.finish().unwrap();
+    assert_eq!(buffer, generate_png_with_compressor(Box::new(FlateCompressor)));
+}
+
+#[test]
+fn test_max_chunk_size() {
+    let (meta, sources) = load_sources();
+    let mut expected = vec![];
+    generate_png(&mut expected, &sources, meta.clone(), None);
+
+    let options = EncoderOptions { max_chunk_size: Some(1000), ..Default::default() };
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, Some(&frame), None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let chunks = read_chunks(&buffer);
+    assert!(chunks.iter().all(|(_, data)| data.len() <= 1000));
+    assert!(1 < chunks.iter().filter(|(chunk_type, _)| chunk_type == "IDAT").count());
+    assert!(3 < chunks.iter().filter(|(chunk_type, _)| chunk_type == "fdAT").count());
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    let frames: Vec<Vec<u8>> = decoded.frames.into_iter().map(|it| it.image_data).collect();
+    assert_eq!(frames, sources);
+    assert!(expected.len() < buffer.len());
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_max_chunk_size_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let options = EncoderOptions { max_chunk_size: Some(4), ..Default::default() };
+    let mut buffer = vec![];
+    Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+}
+
+#[cfg(feature = "zopfli")]
+#[test]
+fn test_zopfli_compressor() {
+    let compressor = apng_encoder::ZopfliCompressor { iterations: 1 };
+    let zopfli = generate_png_with_compressor(Box::new(compressor));
+    let flate = generate_png_with_compressor(Box::new(FlateCompressor));
+    assert!(zopfli.len() <= flate.len());
+}
+
+fn generate_png_with_strategy(filename: &str, filter_strategy: FilterStrategy) -> usize {
+    let (meta, sources) = load_sources();
+    let options = EncoderOptions { filter_strategy, ..Default::default() };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, None, None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+    create_file(filename).write_all(&buffer).unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    for (frame, source) in decoded.frames.iter().zip(sources.iter()) {
+        assert_eq!(&frame.image_data, source);
+    }
+    buffer.len()
+}
+
+#[test]
+fn test_adaptive_filter() {
+    let adaptive = generate_png_with_strategy("cherenkov-adaptive.png", FilterStrategy::Adaptive);
+    let brute_force = generate_png_with_strategy("cherenkov-brute-force.png", FilterStrategy::BruteForce);
+    let (meta, sources) = load_sources();
+    let mut none = vec![];
+    generate_png(&mut none, &sources, meta, Some(Filter::None));
+    assert!(adaptive < none.len());
+    assert!(brute_force < none.len());
+}
+
+fn encode_bit_packed(meta: Meta, image_data: &[u8], options: EncoderOptions) -> Vec<u8> {
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    if let Color::Palette(_) = encoder.meta().color {
+        encoder.write_palette(&[0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0xFF], None).unwrap();
+    }
+    encoder.write_frame(image_data, None, None, None).unwrap();
+    encoder.finish().unwrap();
+    buffer
+}
+
+#[test]
+fn test_generate_bit_packed_grayscale() {
+    let meta = Meta { width: 10, height: 2, color: Color::Grayscale(1), frames: 1, plays: None };
+    let packed = encode_bit_packed(meta.clone(), &[0b1010_1010, 0b1100_0000, 0b0101_0101, 0b0100_0000], EncoderOptions::default());
+    let unpacked = encode_bit_packed(
+        meta,
+        &[1, 0, 1, 0, 1, 0, 1, 0, 1, 1,   0, 1, 0, 1, 0, 1, 0, 1, 0, 1],
+        EncoderOptions { unpacked_samples: true, ..Default::default() });
+    assert_eq!(packed, unpacked);
+    create_file("grayscale-1bit.png").write_all(&packed).unwrap();
+
+    let decoded = decode(&mut packed.as_slice()).unwrap();
+    assert_eq!(decoded.frames[0].image_data, &[0b1010_1010, 0b1100_0000, 0b0101_0101, 0b0100_0000]);
+}
+
+#[test]
+fn test_generate_bit_packed_palette() {
+    let meta = Meta { width: 3, height: 2, color: Color::Palette(2), frames: 1, plays: None };
+    let options = EncoderOptions { unpacked_samples: true, ..Default::default() };
+    let buffer = encode_bit_packed(meta, &[0, 1, 2,   2, 1, 0], options);
+    create_file("palette-2bit.png").write_all(&buffer).unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[0].image_data, &[0b0001_1000, 0b1001_0000]);
+}
+
+#[test]#[should_panic(expected="InvalidPaletteIndex(3)")]
+fn test_bit_packed_palette_index_validation() {
+    let meta = Meta { width: 3, height: 1, color: Color::Palette(2), frames: 1, plays: None };
+    encode_bit_packed(meta, &[0b0001_1100], EncoderOptions::default());
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_unpacked_sample_validation() {
+    let meta = Meta { width: 2, height: 1, color: Color::Grayscale(4), frames: 1, plays: None };
+    encode_bit_packed(meta, &[0x0F, 0x10], EncoderOptions { unpacked_samples: true, ..Default::default() });
+}
+
+#[test]
+fn test_generate_padded_rows() {
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let image_data = [
+        1, 2, 3, 4, 5, 6, 0xFF, 0xFF,
+        7, 8, 9, 10, 11, 12, 0xFF, 0xFF,
+    ];
+    encoder.write_frame(&image_data, None, Some(Filter::Paeth), Some(8)).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[0].image_data, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+}
+
+#[test]
+fn test_generate_padded_rows_without_last_padding() {
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let image_data = [
+        1, 2, 3, 4, 5, 6, 0xFF, 0xFF,
+        7, 8, 9, 10, 11, 12,
+    ];
+    encoder.write_frame(&image_data, None, None, Some(8)).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[0].image_data, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+}
+
+#[test]
+fn test_generate_interlaced() {
+    let (meta, image_data) = load_source("test-files/1.png");
+    let mut buffer = vec![];
+    let options = EncoderOptions { interlaced: true, ..Default::default() };
+    let mut encoder = Encoder::create_with_options(&mut buffer, Meta { frames: 1, ..meta }, options).unwrap();
+    encoder.write_default_image(&image_data, None, None).unwrap();
+    let frame = Frame { width: Some(3), height: Some(2), x: Some(5), y: Some(7), ..Default::default() };
+    encoder.write_frame(&image_data[.. 18], Some(&frame), None, None).unwrap();
+    encoder.finish().unwrap();
+    create_file("interlaced.png").write_all(&buffer).unwrap();
+
+    let decoder = PNGDecoder::new(buffer.as_slice()).unwrap();
+    assert_eq!(decoder.read_image().unwrap(), image_data);
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert!(decoded.interlaced);
+    assert_eq!(decoded.default_image.unwrap(), image_data);
+    assert_eq!(decoded.frames[0].image_data, &image_data[.. 18]);
+}
+
+#[test]
+fn test_generate_interlaced_bit_packed() {
+    let meta = Meta { width: 10, height: 3, color: Color::Grayscale(1), frames: 1, plays: None };
+    let image_data = [0b1010_1010, 0b1100_0000, 0b0101_0101, 0b0100_0000, 0b1111_0000, 0b1000_0000];
+    let buffer = encode_bit_packed(meta, &image_data, EncoderOptions { interlaced: true, ..Default::default() });
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[0].image_data, &image_data);
+}
+
 #[test]
 fn test_generate_png_without_filter() {
     test_generate_png("cherenkov-none.png", Some(Filter::None));
@@ -243,8 +774,7 @@ fn test_generate_offset() {
     };
     let mut encoder = Encoder::create(&mut file, meta).unwrap();
 
-    let mut buffer = vec![];
-    buffer.resize((WIDTH * HEIGHT) as usize, 0);
+    let buffer = vec![0; (WIDTH * HEIGHT) as usize];
     let frame = Frame { delay: Some(Delay::new(1, 1)), ..Default::default() };
     encoder.write_frame(&buffer, Some(&frame), None, None).unwrap();
 
@@ -317,8 +847,7 @@ fn test_generate_shida() {
     let frame = Frame { delay: Some(Delay::new(1, 1)), ..Default::default() };
     let mut encoder = Encoder::create(&mut file, meta).unwrap();
     for i in 0 .. frames {
-        let mut buffer = vec![];
-        buffer.resize(WIDTH * HEIGHT * PX, 0);
+        let mut buffer = vec![0; WIDTH * HEIGHT * PX];
         f(&mut rng, buffer.as_mut_slice(), 10 + i as i64 * 5, 0.0, 0.0);
         encoder.write_frame(&buffer, Some(&frame), None, None).unwrap();
     }
@@ -342,8 +871,7 @@ fn test_generate_default_image() {
     };
     let mut encoder = Encoder::create(&mut file, meta).unwrap();
 
-    let mut buffer = vec![];
-    buffer.resize((WIDTH * HEIGHT) as usize, 0);
+    let mut buffer = vec![0; (WIDTH * HEIGHT) as usize];
     encoder.write_default_image(&buffer, None, None).unwrap();
     for i in 0 .. frames as usize {
         for (index, it) in buffer.iter_mut().enumerate() {
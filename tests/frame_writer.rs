@@ -0,0 +1,221 @@
+use rand::prelude::*;
+
+use apng_encoder::{decode, ApngError, Encoder, EncoderOptions, Filter, FilterStrategy, StoredCompressor};
+use apng_encoder::{Color, Frame, Meta};
+
+mod common;
+use common::read_chunks;
+
+
+
+fn chunk_types(png: &[u8]) -> Vec<String> {
+    read_chunks(png).into_iter().map(|it| it.0).collect()
+}
+
+fn random_image(width: usize, height: usize, pixel_bytes: usize) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0 .. width * height * pixel_bytes).map(|_| rng.gen()).collect()
+}
+
+#[test]
+fn test_frame_writer() {
+    let meta = Meta { width: 300, height: 200, color: Color::RGB(8), frames: 2, plays: None };
+    let first = random_image(300, 200, 3);
+    let second = random_image(100, 50, 3);
+    let frame = Frame { x: Some(10), y: Some(20), width: Some(100), height: Some(50), ..Default::default() };
+
+    for filter in &[None, Some(Filter::Paeth)] {
+        let mut buffer = vec![];
+        let mut encoder = Encoder::create(&mut buffer, meta.clone()).unwrap();
+        let mut writer = encoder.frame_writer(None, *filter).unwrap();
+        for row in first.chunks(300 * 3) {
+            writer.write_row(row).unwrap();
+        }
+        writer.finish().unwrap();
+        let mut writer = encoder.frame_writer(Some(&frame), *filter).unwrap();
+        for row in second.chunks(100 * 3) {
+            writer.write_row(row).unwrap();
+        }
+        writer.finish().unwrap();
+        encoder.finish().unwrap();
+
+        // Random pixels are not compressed, so the image data is split into some chunks
+        let types = chunk_types(&buffer);
+        assert!(2 < types.iter().filter(|it| *it == "IDAT").count());
+        assert_eq!(types.iter().filter(|it| *it == "fdAT").count(), 1);
+
+        let decoded = decode(&mut buffer.as_slice()).unwrap();
+        assert_eq!(decoded.frames[0].image_data, first);
+        assert_eq!(decoded.frames[1].image_data, second);
+        assert_eq!(decoded.frames[1].frame.x, Some(10));
+    }
+}
+
+#[test]
+fn test_default_image_writer() {
+    let meta = Meta { width: 4, height: 3, color: Color::Grayscale(2), frames: 1, plays: None };
+    let options = EncoderOptions { unpacked_samples: true, filter_strategy: FilterStrategy::BruteForce, ..Default::default() };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    let mut writer = encoder.default_image_writer(None).unwrap();
+    for row in &[[0, 1, 2, 3], [3, 2, 1, 0], [0, 0, 3, 3]] {
+        writer.write_row(row).unwrap();
+    }
+    writer.finish().unwrap();
+    encoder.write_frame(&[0; 4 * 3], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(chunk_types(&buffer), &["IHDR", "acTL", "IDAT", "fcTL", "fdAT", "IEND"]);
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.default_image.unwrap(), &[0b0001_1011, 0b1110_0100, 0b0000_1111]);
+}
+
+#[test]
+fn test_frame_writer_with_stride() {
+    let meta = Meta { width: 2, height: 2, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let mut writer = encoder.frame_writer(None, Some(Filter::Up)).unwrap();
+    writer.write_row(&[1, 2, 0xFF]).unwrap();
+    writer.write_row(&[3, 4, 0xFF]).unwrap();
+    writer.finish().unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(decode(&mut buffer.as_slice()).unwrap().frames[0].image_data, &[1, 2, 3, 4]);
+}
+
+#[test]#[should_panic(expected="TooSmallImage")]
+fn test_frame_writer_not_enough_rows_validation() {
+    let meta = Meta { width: 1, height: 2, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    writer.write_row(&[0]).unwrap();
+    writer.finish().unwrap();
+}
+
+#[test]
+fn test_frame_writer_missing_rows() {
+    let meta = Meta { width: 1, height: 2, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    writer.write_row(&[1]).unwrap();
+    let error = writer.finish().unwrap_err();
+    assert!(matches!(error.error(), ApngError::TooSmallImage));
+    let mut writer = error.into_writer();
+    writer.write_row(&[2]).unwrap();
+    writer.finish().unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(decode(&mut buffer.as_slice()).unwrap().frames[0].image_data, &[1, 2]);
+}
+
+#[test]#[should_panic(expected="UnfinishedFrame")]
+fn test_frame_writer_failed_finish_validation() {
+    let meta = Meta { width: 1, height: 2, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    writer.write_row(&[0]).unwrap();
+    assert!(writer.finish().is_err());
+    encoder.into_inner().unwrap();
+}
+
+#[test]#[should_panic(expected="UnfinishedFrame")]
+fn test_frame_writer_drop_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.frame_writer(None, None).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_frame_writer_too_many_rows_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    writer.write_row(&[0]).unwrap();
+    writer.write_row(&[0]).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_frame_writer_rectangle_validation() {
+    let meta = Meta { width: 2, height: 2, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0; 4], None, None, None).unwrap();
+    let frame = Frame { x: Some(1), width: Some(2), height: Some(1), ..Default::default() };
+    encoder.frame_writer(Some(&frame), None).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_frame_writer_rectangle_overflow_validation() {
+    let meta = Meta { width: 2, height: 2, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0; 4], None, None, None).unwrap();
+    let frame = Frame { x: Some(0xFFFFFFFF), width: Some(2), height: Some(1), ..Default::default() };
+    encoder.frame_writer(Some(&frame), None).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_frame_writer_short_row_validation() {
+    let meta = Meta { width: 2, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    writer.write_row(&[0; 5]).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidPaletteIndex(2)")]
+fn test_frame_writer_palette_index_validation() {
+    let meta = Meta { width: 2, height: 1, color: Color::Palette(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_palette(&[0xFF, 0x00, 0x00,   0x00, 0xFF, 0x00], None).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    writer.write_row(&[1, 2]).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_frame_writer_interlaced_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let options = EncoderOptions { interlaced: true, ..Default::default() };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    encoder.frame_writer(None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_frame_writer_compressor_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.set_compressor(Box::new(StoredCompressor));
+    encoder.frame_writer(None, None).unwrap();
+}
+
+#[test]
+fn test_frame_writer_max_chunk_size() {
+    let meta = Meta { width: 100, height: 100, color: Color::RGB(8), frames: 1, plays: None };
+    let image_data = random_image(100, 100, 3);
+    let options = EncoderOptions { max_chunk_size: Some(1000), ..Default::default() };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create_with_options(&mut buffer, meta, options).unwrap();
+    let mut writer = encoder.frame_writer(None, None).unwrap();
+    for row in image_data.chunks(100 * 3) {
+        writer.write_row(row).unwrap();
+    }
+    writer.finish().unwrap();
+    encoder.finish().unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let idat: Vec<usize> = chunks.iter().filter(|it| it.0 == "IDAT").map(|it| it.1.len()).collect();
+    // All but the last chunk are full
+    assert!(30 < idat.len());
+    assert!(idat[.. idat.len() - 1].iter().all(|it| *it == 1000));
+    assert_eq!(decode(&mut buffer.as_slice()).unwrap().frames[0].image_data, image_data);
+}
@@ -0,0 +1,73 @@
+use apng_encoder::{decode, Encoder, MergingEncoder};
+use apng_encoder::{BlendOperator, Color, Delay, Frame, Meta};
+
+
+
+fn merge(frames: &[(u8, Frame)]) -> Vec<Frame> {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 0, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = MergingEncoder::new(Encoder::create_buffered(&mut buffer, meta).unwrap()).unwrap();
+    for (pixel, frame) in frames {
+        encoder.write_frame(&[*pixel], Some(frame), None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.meta.frames as usize, decoded.frames.len());
+    decoded.frames.into_iter().map(|it| it.frame).collect()
+}
+
+fn delayed(numerator: u16, denominator: u16) -> Frame {
+    Frame { delay: Some(Delay::new(numerator, denominator)), ..Default::default() }
+}
+
+#[test]
+fn test_merge_identical_frames() {
+    let frames = merge(&[
+        (0, delayed(1, 10)),
+        (0, delayed(1, 10)),
+        (0, delayed(1, 10)),
+        (1, delayed(1, 3)),
+        (1, delayed(1, 2)),
+        (0, delayed(1, 0)),
+    ]);
+    let delays: Vec<_> = frames.iter().map(|it| it.delay.unwrap()).collect();
+    assert_eq!(delays, vec![Delay::new(3, 10), Delay::new(5, 6), Delay::new(1, 100)]);
+}
+
+#[test]
+fn test_merge_delay_overflow() {
+    let frames = merge(&[
+        (0, delayed(1, 65535)),
+        (0, delayed(1, 65534)),
+    ]);
+    let delay = frames[0].delay.unwrap();
+    let expected = 1.0 / 65535.0 + 1.0 / 65534.0;
+    let actual = f64::from(delay.numerator) / f64::from(delay.denominator);
+    assert!((expected - actual).abs() < 1e-6);
+}
+
+#[test]
+fn test_merge_exact_delay() {
+    // 1/256 + 1/257 does not fit in u16, but the sum of all does
+    let frames = merge(&[
+        (0, delayed(1, 256)),
+        (0, delayed(1, 257)),
+        (0, delayed(255, 256)),
+    ]);
+    assert_eq!(frames[0].delay, Some(Delay::new(258, 257)));
+}
+
+#[test]
+fn test_not_merge_over_blend() {
+    let frame = Frame { blend_operator: Some(BlendOperator::Over), ..delayed(1, 10) };
+    let frames = merge(&[(0, frame.clone()), (0, frame)]);
+    assert_eq!(frames.len(), 2);
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_merger_frame_count_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let _ = MergingEncoder::new(Encoder::create(&mut buffer, meta).unwrap()).unwrap();
+}
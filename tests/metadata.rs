@@ -0,0 +1,150 @@
+use apng_encoder::{decode, Background, Compositor, Encoder, PhysicalDimensions, Timestamp, TransparentColor, Unit};
+use apng_encoder::{Color, Meta};
+
+mod common;
+use common::read_chunks;
+
+
+
+fn timestamp() -> Timestamp {
+    Timestamp { year: 2019, month: 3, day: 9, hour: 12, minute: 34, second: 56 }
+}
+
+#[test]
+fn test_metadata_chunks() {
+    let meta = Meta { width: 2, height: 1, color: Color::Palette(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_physical_dimensions(&PhysicalDimensions { x: 3780, y: 3780, unit: Unit::Meter }).unwrap();
+    encoder.write_palette(&[0xFF, 0x00, 0x00,   0x00, 0x00, 0xFF], Some(&[0x00])).unwrap();
+    encoder.write_background(Background::PaletteIndex(1)).unwrap();
+    encoder.write_frame(&[0, 1], None, None, None).unwrap();
+    encoder.write_frame(&[1, 0], None, None, None).unwrap();
+    encoder.write_timestamp(&timestamp()).unwrap();
+    encoder.finish().unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "pHYs", "PLTE", "tRNS", "bKGD", "fcTL", "IDAT", "fcTL", "fdAT", "tIME", "IEND"]);
+    assert_eq!(chunks[2].1, &[0x00, 0x00, 0x0E, 0xC4, 0x00, 0x00, 0x0E, 0xC4, 0x01]);
+    assert_eq!(chunks[5].1, &[1]);
+    assert_eq!(chunks[10].1, &[0x07, 0xE3, 3, 9, 12, 34, 56]);
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.background, Some(Background::PaletteIndex(1)));
+    let canvases = Compositor::composite_decoded(&decoded).unwrap();
+    // Transparent red is flattened onto blue
+    assert_eq!(canvases[0], &[0x00, 0x00, 0xFF, 0xFF,   0x00, 0x00, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_rgb_background() {
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(16), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_background(Background::RGB(0xFFFF, 0x8000, 0x0000)).unwrap();
+    encoder.write_frame(&[0; 6], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let (_, data) = read_chunks(&buffer).into_iter().find(|it| it.0 == "bKGD").unwrap();
+    assert_eq!(data, &[0xFF, 0xFF, 0x80, 0x00, 0x00, 0x00]);
+    assert_eq!(decode(&mut buffer.as_slice()).unwrap().background, Some(Background::RGB(0xFFFF, 0x8000, 0x0000)));
+}
+
+#[test]#[should_panic(expected="InvalidColor")]
+fn test_background_color_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_background(Background::Gray(0)).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_background_sample_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(4), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_background(Background::Gray(16)).unwrap();
+}
+
+#[test]#[should_panic(expected="NoPalette")]
+fn test_background_before_palette_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Palette(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_background(Background::PaletteIndex(0)).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunkPosition(\"pHYs\")")]
+fn test_physical_dimensions_position_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    encoder.write_physical_dimensions(&PhysicalDimensions { x: 1, y: 1, unit: Unit::Unknown }).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_timestamp_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_timestamp(&Timestamp { month: 13, ..timestamp() }).unwrap();
+}
+
+#[test]
+fn test_transparent_color() {
+    let meta = Meta { width: 2, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_transparent_color(TransparentColor::RGB(0x00, 0xFF, 0x00)).unwrap();
+    encoder.write_frame(&[0xFF, 0x00, 0x00,   0x00, 0xFF, 0x00], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "tRNS", "fcTL", "IDAT", "IEND"]);
+    assert_eq!(chunks[2].1, &[0x00, 0x00, 0x00, 0xFF, 0x00, 0x00]);
+
+    let canvases = Compositor::composite_decoded(&decode(&mut buffer.as_slice()).unwrap()).unwrap();
+    assert_eq!(canvases[0], &[0xFF, 0x00, 0x00, 0xFF,   0x00, 0xFF, 0x00, 0x00]);
+}
+
+#[test]
+fn test_transparent_gray() {
+    let meta = Meta { width: 4, height: 1, color: Color::Grayscale(2), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_transparent_color(TransparentColor::Gray(3)).unwrap();
+    encoder.write_frame(&[0b0001_1011], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let canvases = Compositor::composite_decoded(&decode(&mut buffer.as_slice()).unwrap()).unwrap();
+    let alpha: Vec<u8> = canvases[0].chunks(4).map(|it| it[3]).collect();
+    assert_eq!(alpha, &[0xFF, 0xFF, 0xFF, 0x00]);
+}
+
+#[test]#[should_panic(expected="InvalidColor")]
+fn test_transparent_color_with_alpha_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::GrayscaleA(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_transparent_color(TransparentColor::Gray(0)).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_transparent_color_sample_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::RGB(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_transparent_color(TransparentColor::RGB(0x100, 0, 0)).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidChunkPosition(\"tRNS\")")]
+fn test_transparent_color_position_validation() {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_frame(&[0], None, None, None).unwrap();
+    encoder.write_transparent_color(TransparentColor::Gray(0)).unwrap();
+}
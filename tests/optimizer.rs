@@ -0,0 +1,173 @@
+use apng_encoder::{decode, Compositor, Encoder, OptimizedEncoder, OptimizerOptions};
+use apng_encoder::{BlendOperator, DisposeOperator};
+use apng_encoder::{Color, Delay, Meta};
+
+
+
+const WIDTH: usize = 20;
+const HEIGHT: usize = 10;
+
+
+/// Grayscale frames with a moving white square
+fn moving_square(frames: usize) -> Vec<Vec<u8>> {
+    (0 .. frames).map(|i| {
+        let mut canvas = vec![0x40; WIDTH * HEIGHT];
+        for y in 2 .. 5 {
+            for x in i * 2 .. i * 2 + 3 {
+                canvas[y * WIDTH + x] = 0xFF;
+            }
+        }
+        canvas
+    }).collect()
+}
+
+/// Grayscale frames with a white square at the top left only in the second frame, and at the bottom right in the third
+fn flashing_squares() -> Vec<Vec<u8>> {
+    let mut sources = vec![vec![0x40; WIDTH * HEIGHT]; 3];
+    for y in 0 .. 2 {
+        for x in 0 .. 2 {
+            sources[1][y * WIDTH + x] = 0xFF;
+            sources[2][(HEIGHT - 1 - y) * WIDTH + WIDTH - 1 - x] = 0xFF;
+        }
+    }
+    sources
+}
+
+fn to_rgba(grayscale: &[u8]) -> Vec<u8> {
+    grayscale.iter().flat_map(|it| vec![*it, *it, *it, 0xFF]).collect()
+}
+
+#[test]
+fn test_optimized_frames() {
+    let sources = moving_square(4);
+    let meta = Meta { width: WIDTH as u32, height: HEIGHT as u32, color: Color::Grayscale(8), frames: 5, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = OptimizedEncoder::new(Encoder::create(&mut buffer, meta).unwrap()).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, Some(Delay::new(1, 10)), None, None).unwrap();
+    }
+    // Not changed
+    encoder.write_frame(&sources[3], Some(Delay::new(1, 10)), None, None).unwrap();
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    let frames: Vec<_> = decoded.frames.iter().map(|it| &it.frame).collect();
+    assert_eq!((frames[0].width, frames[0].height), (Some(WIDTH as u32), Some(HEIGHT as u32)));
+    assert_eq!((frames[1].x, frames[1].y, frames[1].width, frames[1].height), (Some(0), Some(2), Some(5), Some(3)));
+    assert_eq!((frames[4].width, frames[4].height), (Some(1), Some(1)));
+
+    let canvases = Compositor::composite_decoded(&decoded).unwrap();
+    for (canvas, source) in canvases.iter().zip(sources.iter().chain(sources.last())) {
+        assert_eq!(canvas, &to_rgba(source));
+    }
+}
+
+#[test]
+fn test_optimized_frames_with_row_stride() {
+    let sources = moving_square(2);
+    let meta = Meta { width: WIDTH as u32, height: HEIGHT as u32, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = OptimizedEncoder::new(Encoder::create_buffered(&mut buffer, meta).unwrap()).unwrap();
+    for source in &sources {
+        let padded: Vec<u8> = source.chunks(WIDTH).flat_map(|it| [it, &[0, 0]].concat()).collect();
+        encoder.write_frame(&padded, None, None, Some(WIDTH + 2)).unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let canvases = Compositor::composite_decoded(&decode(&mut buffer.as_slice()).unwrap()).unwrap();
+    assert_eq!(canvases[1], to_rgba(&sources[1]));
+}
+
+#[test]#[should_panic(expected="InvalidColor")]
+fn test_optimizer_color_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Grayscale(1), frames: 1, plays: None };
+    let _ = OptimizedEncoder::new(Encoder::create(&mut buffer, meta).unwrap()).unwrap();
+}
+
+fn encode_rgba(sources: &[Vec<u8>]) -> Vec<u8> {
+    let meta = Meta { width: WIDTH as u32, height: HEIGHT as u32, color: Color::RGBA(8), frames: sources.len() as u32, plays: None };
+    let options = OptimizerOptions { transparent_pixels: true };
+    let mut buffer = vec![];
+    let mut encoder = OptimizedEncoder::with_options(Encoder::create(&mut buffer, meta).unwrap(), options).unwrap();
+    for source in sources {
+        encoder.write_frame(source, None, None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+    buffer
+}
+
+#[test]
+fn test_transparent_pixels() {
+    let sources: Vec<Vec<u8>> = moving_square(3).iter().map(|it| to_rgba(it)).collect();
+    let buffer = encode_rgba(&sources);
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[1].frame.blend_operator, Some(BlendOperator::Over));
+    // (x=2, y=2) is not changed
+    assert_eq!(decoded.frames[1].image_data[8 .. 12], [0, 0, 0, 0]);
+
+    let canvases = Compositor::composite_decoded(&decoded).unwrap();
+    for (canvas, source) in canvases.iter().zip(sources.iter()) {
+        assert_eq!(canvas, source);
+    }
+}
+
+#[test]
+fn test_transparent_pixels_fallback() {
+    let mut sources: Vec<Vec<u8>> = moving_square(2).iter().map(|it| to_rgba(it)).collect();
+    // Translucent pixel over opaque pixel
+    sources[1][3] = 0x80;
+    let buffer = encode_rgba(&sources);
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    assert_eq!(decoded.frames[1].frame.blend_operator, Some(BlendOperator::Source));
+
+    let canvases = Compositor::composite_decoded(&decoded).unwrap();
+    assert_eq!(canvases[1], sources[1]);
+}
+
+#[test]
+fn test_dispose_previous() {
+    let sources = flashing_squares();
+    let meta = Meta { width: WIDTH as u32, height: HEIGHT as u32, color: Color::Grayscale(8), frames: 3, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = OptimizedEncoder::new(Encoder::create(&mut buffer, meta).unwrap()).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, None, None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    let frames: Vec<_> = decoded.frames.iter().map(|it| &it.frame).collect();
+    assert_eq!(frames[0].dispose_operator, Some(DisposeOperator::None));
+    assert_eq!(frames[1].dispose_operator, Some(DisposeOperator::Previous));
+    assert_eq!(frames[2].dispose_operator, Some(DisposeOperator::None));
+    assert_eq!((frames[2].x, frames[2].y, frames[2].width, frames[2].height), (Some(WIDTH as u32 - 2), Some(HEIGHT as u32 - 2), Some(2), Some(2)));
+
+    let canvases = Compositor::composite_decoded(&decoded).unwrap();
+    for (canvas, source) in canvases.iter().zip(sources.iter()) {
+        assert_eq!(canvas, &to_rgba(source));
+    }
+}
+
+#[test]
+fn test_dispose_background() {
+    // Squares on the transparent canvas
+    let sources: Vec<Vec<u8>> = flashing_squares().iter().map(|source| {
+        source.iter().flat_map(|it| if *it == 0xFF { vec![0xFF; 4] } else { vec![0; 4] }).collect()
+    }).collect();
+    let buffer = encode_rgba(&sources);
+
+    let decoded = decode(&mut buffer.as_slice()).unwrap();
+    let frames: Vec<_> = decoded.frames.iter().map(|it| &it.frame).collect();
+    assert_eq!(frames[1].dispose_operator, Some(DisposeOperator::Background));
+    assert_eq!((frames[2].x, frames[2].y, frames[2].width, frames[2].height), (Some(WIDTH as u32 - 2), Some(HEIGHT as u32 - 2), Some(2), Some(2)));
+    // Over the cleared region
+    assert_eq!(frames[2].blend_operator, Some(BlendOperator::Over));
+
+    let canvases = Compositor::composite_decoded(&decoded).unwrap();
+    for (canvas, source) in canvases.iter().zip(sources.iter()) {
+        assert_eq!(canvas, source);
+    }
+}
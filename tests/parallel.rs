@@ -0,0 +1,66 @@
+#![cfg(feature = "parallel")]
+
+use apng_encoder::{Encoder, Filter, ParallelEncoder};
+use apng_encoder::{Color, Delay, Frame, Meta};
+
+mod common;
+use common::load_sources;
+
+
+
+fn generate(sources: &[Vec<u8>], meta: Meta, queue_size: Option<usize>) -> Vec<u8> {
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut buffer = vec![];
+    {
+        let encoder = Encoder::create(&mut buffer, meta).unwrap();
+        match queue_size {
+            Some(queue_size) => {
+                let mut encoder = ParallelEncoder::with_queue_size(encoder, queue_size);
+                encoder.write_default_image(&sources[0], Some(Filter::Up), None).unwrap();
+                for source in sources {
+                    encoder.write_frame(source, Some(&frame), None, None).unwrap();
+                }
+                encoder.finish().unwrap();
+            },
+            None => {
+                let mut encoder = encoder;
+                encoder.write_default_image(&sources[0], Some(Filter::Up), None).unwrap();
+                for source in sources {
+                    encoder.write_frame(source, Some(&frame), None, None).unwrap();
+                }
+                encoder.finish().unwrap();
+            },
+        }
+    }
+    buffer
+}
+
+#[test]
+fn test_parallel_output_is_identical() {
+    let (meta, sources) = load_sources();
+    let expected = generate(&sources, meta.clone(), None);
+    for queue_size in &[1, 3, 4, 16] {
+        assert_eq!(generate(&sources, meta.clone(), Some(*queue_size)), expected);
+    }
+}
+
+#[test]#[should_panic(expected="TooManyFrames(1, 2)")]
+fn test_parallel_many_frames_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut encoder = ParallelEncoder::new(Encoder::create(&mut buffer, meta).unwrap());
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    encoder.write_frame(&[0xFF], None, None, None).unwrap();
+    encoder.finish().unwrap();
+}
+
+#[test]#[should_panic(expected="NotEnoughFrames(2, 1)")]
+fn test_parallel_default_image_rectangle_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 1, color: Color::Grayscale(8), frames: 2, plays: None };
+    let mut encoder = ParallelEncoder::new(Encoder::create(&mut buffer, meta).unwrap());
+    let frame = Frame { x: Some(1), width: Some(1), ..Default::default() };
+    assert!(encoder.write_frame(&[0x00], Some(&frame), None, None).is_err());
+    encoder.write_frame(&[0x00, 0xFF], None, None, None).unwrap();
+    encoder.finish().unwrap();
+}
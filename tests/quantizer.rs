@@ -0,0 +1,117 @@
+use std::fs::{create_dir, File};
+
+use apng_encoder::{Color, Delay, Frame, Meta};
+use apng_encoder::{QuantizedEncoder, QuantizerOptions};
+
+mod common;
+use common::load_sources;
+
+
+
+fn create_file(filename: &str) -> File {
+    let _ = create_dir("test-output");
+    File::create(format!("test-output/{}", filename)).unwrap()
+}
+
+fn find_chunk<'a>(png: &'a [u8], chunk_type: &[u8]) -> Option<&'a [u8]> {
+    let position = png.windows(4).position(|it| it == chunk_type)?;
+    let length = u32::from_be_bytes([png[position - 4], png[position - 3], png[position - 2], png[position - 1]]) as usize;
+    Some(&png[position + 4 .. position + 4 + length])
+}
+
+#[test]#[should_panic(expected="InvalidColor")]
+fn test_quantizer_color_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::Grayscale(8), frames: 1, plays: None };
+    let _ = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidArgument")]
+fn test_quantizer_colors_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let options = QuantizerOptions { colors: 257, ..Default::default() };
+    let _ = QuantizedEncoder::create(&mut buffer, meta, options).unwrap();
+}
+
+#[test]
+fn test_quantize_exact_colors() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 1, color: Color::RGBA(8), frames: 2, plays: None };
+    let mut encoder = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+    encoder.write_frame(&[0xFF, 0x00, 0x00, 0xFF,   0x00, 0x00, 0x00, 0x00], None, None, None).unwrap();
+    encoder.write_frame(&[0x00, 0x00, 0x00, 0x00,   0x00, 0xFF, 0x00, 0xFF], None, None, None).unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(find_chunk(&buffer, b"IHDR").unwrap()[8 .. 10], [8, 3]);
+    assert_eq!(find_chunk(&buffer, b"PLTE").unwrap(), &[0x00, 0x00, 0x00,   0x00, 0xFF, 0x00,   0xFF, 0x00, 0x00]);
+    assert_eq!(find_chunk(&buffer, b"tRNS").unwrap(), &[0x00]);
+}
+
+#[test]
+fn test_quantize_with_dithering() {
+    let (meta, sources) = load_sources();
+    let options = QuantizerOptions { colors: 64, dithering: true };
+    let frame = Frame { delay: Some(Delay::new(1, 10)), ..Default::default() };
+    let mut file = create_file("quantized.png");
+    let mut encoder = QuantizedEncoder::create(&mut file, meta, options).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, Some(&frame), None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+}
+
+#[test]
+fn test_quantize_palette_size() {
+    let (meta, sources) = load_sources();
+    let options = QuantizerOptions { colors: 16, dithering: false };
+    let mut buffer = vec![];
+    let mut encoder = QuantizedEncoder::create(&mut buffer, meta, options).unwrap();
+    for source in &sources {
+        encoder.write_frame(source, None, None, None).unwrap();
+    }
+    encoder.finish().unwrap();
+
+    let palette = find_chunk(&buffer, b"PLTE").unwrap();
+    assert!(palette.len() <= 16 * 3);
+    assert!(find_chunk(&buffer, b"tRNS").is_none());
+}
+
+#[test]#[should_panic(expected="TooSmallImage")]
+fn test_quantizer_image_size_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let mut encoder = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+    encoder.write_frame(&[0; 3 * 3], None, None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="TooLargeImage")]
+fn test_quantizer_rectangle_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 2, plays: None };
+    let mut encoder = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+    encoder.write_frame(&[0; 2 * 2 * 3], None, None, None).unwrap();
+    let frame = Frame { x: Some(1), ..Default::default() };
+    encoder.write_frame(&[0; 2 * 2 * 3], Some(&frame), None, None).unwrap();
+}
+
+#[test]#[should_panic(expected="InvalidDefaultImageRectangle")]
+fn test_quantizer_default_image_rectangle_validation() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 2, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let mut encoder = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+    let frame = Frame { width: Some(1), ..Default::default() };
+    encoder.write_frame(&[0; 2 * 3], Some(&frame), None, None).unwrap();
+}
+
+#[test]
+fn test_quantize_padded_rows() {
+    let mut buffer = vec![];
+    let meta = Meta { width: 1, height: 2, color: Color::RGB(8), frames: 1, plays: None };
+    let mut encoder = QuantizedEncoder::create(&mut buffer, meta, QuantizerOptions::default()).unwrap();
+    // The last row has no padding
+    encoder.write_frame(&[0xFF, 0x00, 0x00, 0xEE,   0x00, 0xFF, 0x00], None, None, Some(4)).unwrap();
+    encoder.finish().unwrap();
+
+    assert_eq!(find_chunk(&buffer, b"PLTE").unwrap(), &[0x00, 0xFF, 0x00,   0xFF, 0x00, 0x00]);
+}
@@ -0,0 +1,87 @@
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use apng_encoder::{decode, Encoder, TextFormat};
+use apng_encoder::{Color, Meta};
+
+mod common;
+use common::read_chunks;
+
+
+
+fn inflate(data: &[u8]) -> Vec<u8> {
+    let mut result = vec![];
+    ZlibDecoder::new(data).read_to_end(&mut result).unwrap();
+    result
+}
+
+fn generate_with_text(keyword: &str, text: &str, format: TextFormat) -> Vec<u8> {
+    let meta = Meta { width: 1, height: 1, color: Color::Grayscale(8), frames: 1, plays: None };
+    let mut buffer = vec![];
+    let mut encoder = Encoder::create(&mut buffer, meta).unwrap();
+    encoder.write_text(keyword, text, format).unwrap();
+    encoder.write_frame(&[0x00], None, None, None).unwrap();
+    encoder.finish().unwrap();
+    decode(&mut buffer.as_slice()).unwrap();
+    buffer
+}
+
+#[test]
+fn test_latin1_text() {
+    let buffer = generate_with_text("Copyright", "© anekos", TextFormat::Latin1);
+    let chunks = read_chunks(&buffer);
+    let types: Vec<&str> = chunks.iter().map(|it| it.0.as_str()).collect();
+    assert_eq!(types, &["IHDR", "acTL", "tEXt", "fcTL", "IDAT", "IEND"]);
+    assert_eq!(chunks[2].1, b"Copyright\0\xA9 anekos");
+}
+
+#[test]
+fn test_compressed_latin1_text() {
+    let text = "Lorem ipsum dolor sit amet. ".repeat(10);
+    let buffer = generate_with_text("Description", &text, TextFormat::CompressedLatin1);
+    let (chunk_type, data) = read_chunks(&buffer).into_iter().find(|it| it.0 == "zTXt").unwrap();
+    assert_eq!(chunk_type, "zTXt");
+    assert_eq!(&data[.. 13], b"Description\0\0");
+    assert_eq!(inflate(&data[13 ..]), text.as_bytes());
+}
+
+#[test]
+fn test_utf8_text() {
+    let format = TextFormat::Utf8 { compressed: false, language: "ja-JP".to_owned(), translated_keyword: "作者".to_owned() };
+    let buffer = generate_with_text("Author", "あねこす", format);
+    let (_, data) = read_chunks(&buffer).into_iter().find(|it| it.0 == "iTXt").unwrap();
+    assert_eq!(data, "Author\0\0\0ja-JP\0作者\0あねこす".as_bytes());
+
+    let format = TextFormat::Utf8 { compressed: true, language: "".to_owned(), translated_keyword: "".to_owned() };
+    let buffer = generate_with_text("Author", "あねこす", format);
+    let (_, data) = read_chunks(&buffer).into_iter().find(|it| it.0 == "iTXt").unwrap();
+    assert_eq!(&data[.. 11], b"Author\0\x01\0\0\0");
+    assert_eq!(inflate(&data[11 ..]), "あねこす".as_bytes());
+}
+
+#[test]#[should_panic(expected="InvalidKeyword")]
+fn test_empty_keyword_validation() {
+    generate_with_text("", "text", TextFormat::Latin1);
+}
+
+#[test]#[should_panic(expected="InvalidKeyword")]
+fn test_long_keyword_validation() {
+    generate_with_text(&"k".repeat(80), "text", TextFormat::Latin1);
+}
+
+#[test]#[should_panic(expected="InvalidKeyword")]
+fn test_keyword_space_validation() {
+    generate_with_text("Creation  Time", "text", TextFormat::Latin1);
+}
+
+#[test]#[should_panic(expected="InvalidText")]
+fn test_latin1_text_validation() {
+    generate_with_text("Title", "タイトル", TextFormat::Latin1);
+}
+
+#[test]#[should_panic(expected="InvalidText")]
+fn test_language_validation() {
+    let format = TextFormat::Utf8 { compressed: false, language: "ja_JP".to_owned(), translated_keyword: "".to_owned() };
+    generate_with_text("Title", "タイトル", format);
+}